@@ -0,0 +1,144 @@
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Level {
+    pub price: Decimal,
+    pub qty: Decimal,
+}
+
+/// Resting quantity within some distance of the mid price.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BookDepth {
+    pub bid_qty: Decimal,
+    pub ask_qty: Decimal,
+}
+
+/// Top of an order book: `bids` best (highest) first, `asks` best (lowest) first.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OrderBook {
+    pub bids: Vec<Level>,
+    pub asks: Vec<Level>,
+}
+
+const BPS: Decimal = Decimal::from_parts(10_000, 0, 0, false, 0);
+
+impl OrderBook {
+    pub fn best_bid(&self) -> Option<&Level> {
+        self.bids.first()
+    }
+
+    pub fn best_ask(&self) -> Option<&Level> {
+        self.asks.first()
+    }
+
+    pub fn mid(&self) -> Option<Decimal> {
+        Some((self.best_bid()?.price + self.best_ask()?.price) / Decimal::TWO)
+    }
+
+    /// Best ask minus best bid; negative when the book is crossed.
+    pub fn spread(&self) -> Option<Decimal> {
+        Some(self.best_ask()?.price - self.best_bid()?.price)
+    }
+
+    pub fn spread_bps(&self) -> Option<Decimal> {
+        let mid = self.mid().filter(|m| !m.is_zero())?;
+        Some(self.spread()? / mid * BPS)
+    }
+
+    /// `(bids - asks) / (bids + asks)` over the loaded levels, in `[-1, 1]`.
+    pub fn imbalance(&self) -> Option<Decimal> {
+        let bids: Decimal = self.bids.iter().map(|l| l.qty).sum();
+        let asks: Decimal = self.asks.iter().map(|l| l.qty).sum();
+        let total = bids + asks;
+        if total.is_zero() {
+            return None;
+        }
+        Some((bids - asks) / total)
+    }
+
+    /// Quantity resting within `bps` basis points of the mid price, counting
+    /// only the loaded levels.
+    pub fn depth_within(&self, bps: Decimal) -> Option<BookDepth> {
+        let mid = self.mid()?;
+        let band = mid * bps / BPS;
+        Some(BookDepth {
+            bid_qty: self
+                .bids
+                .iter()
+                .take_while(|l| l.price >= mid - band)
+                .map(|l| l.qty)
+                .sum(),
+            ask_qty: self
+                .asks
+                .iter()
+                .take_while(|l| l.price <= mid + band)
+                .map(|l| l.qty)
+                .sum(),
+        })
+    }
+}
+
+/// Full book as returned by the exchange REST depth endpoint.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DepthSnapshot {
+    pub last_update_id: i64,
+    pub bids: Vec<Level>,
+    pub asks: Vec<Level>,
+}
+
+/// Incremental depth event. A zero `qty` removes the level.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DepthUpdate {
+    /// `U`: first update id in the event.
+    pub first_update_id: i64,
+    /// `u`: final update id in the event.
+    pub final_update_id: i64,
+    /// `pu`: final update id of the previous event, sent by futures streams
+    /// only. Spot streams are sequenced on `first_update_id` instead.
+    pub prev_final_update_id: Option<i64>,
+    pub bids: Vec<Level>,
+    pub asks: Vec<Level>,
+}
+
+/// What happened to a `DepthUpdate`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DepthOutcome {
+    Applied,
+    /// Already covered by the stored book.
+    Stale,
+    /// Not contiguous with the stored book, or no book stored yet; a new
+    /// snapshot is needed.
+    Resync,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn level(price: Decimal, qty: Decimal) -> Level {
+        Level { price, qty }
+    }
+
+    #[test]
+    fn test_metrics() {
+        let book = OrderBook {
+            bids: vec![level(dec!(99.9), dec!(3)), level(dec!(99), dec!(5))],
+            asks: vec![level(dec!(100.1), dec!(1)), level(dec!(101), dec!(7))],
+        };
+        assert_eq!(book.mid(), Some(dec!(100)));
+        assert_eq!(book.spread(), Some(dec!(0.2)));
+        assert_eq!(book.spread_bps(), Some(dec!(20)));
+        assert_eq!(book.imbalance(), Some(dec!(0)));
+        assert_eq!(
+            book.depth_within(dec!(10)),
+            Some(BookDepth {
+                bid_qty: dec!(3),
+                ask_qty: dec!(1),
+            })
+        );
+        assert_eq!(OrderBook::default().mid(), None);
+        assert_eq!(OrderBook::default().imbalance(), None);
+    }
+}
@@ -0,0 +1,257 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use async_trait::async_trait;
+use ecode::Result;
+use redis::{
+    streams::{StreamReadOptions, StreamReadReply},
+    AsyncCommands,
+};
+use tokio::sync::broadcast;
+
+use crate::{cache::RedisQuery, codec::DomainEvent, BusMessage, EventBus, Subscription};
+
+/// How long one `XREADGROUP` waits before polling again.
+const REDIS_BLOCK_MS: usize = 1000;
+const REDIS_READ_COUNT: usize = 100;
+
+/// Topics are stream keys, groups are consumer groups.
+#[async_trait]
+impl EventBus for RedisQuery {
+    async fn publish(&self, topic: &str, event: &DomainEvent) -> Result<String> {
+        let mut conn = self.db.get_async_connection().await?;
+        Ok(conn.xadd(topic, "*", &event.to_stream_fields()?).await?)
+    }
+
+    async fn subscribe(
+        &self,
+        topic: &str,
+        group: &str,
+        consumer: &str,
+    ) -> Result<Box<dyn Subscription>> {
+        self.create_group(topic, group, "$").await?;
+        Ok(Box::new(RedisSubscription {
+            redis: self.clone(),
+            topic: topic.to_string(),
+            group: group.to_string(),
+            consumer: consumer.to_string(),
+            pending_after: Some("0".to_string()),
+            buf: VecDeque::new(),
+        }))
+    }
+}
+
+struct RedisSubscription {
+    redis: RedisQuery,
+    topic: String,
+    group: String,
+    consumer: String,
+    /// While set, `next` replays this consumer's entries that were delivered
+    /// but never acked, e.g. before a restart, starting after this id. Cleared
+    /// once they are all read, then only new entries are read.
+    pending_after: Option<String>,
+    buf: VecDeque<BusMessage>,
+}
+
+#[async_trait]
+impl Subscription for RedisSubscription {
+    async fn next(&mut self) -> Result<Option<BusMessage>> {
+        let opts = StreamReadOptions::default()
+            .group(&self.group, &self.consumer)
+            .block(REDIS_BLOCK_MS)
+            .count(REDIS_READ_COUNT);
+        while self.buf.is_empty() {
+            let from = self.pending_after.clone();
+            let reply: Option<StreamReadReply> = {
+                let mut conn = self.redis.db.get_async_connection().await?;
+                let id = from.as_deref().unwrap_or(">");
+                conn.xread_options(&[&self.topic], &[id], &opts).await?
+            };
+            let entries: Vec<_> = reply
+                .into_iter()
+                .flat_map(|r| r.keys)
+                .flat_map(|k| k.ids)
+                .collect();
+            if from.is_some() {
+                self.pending_after = entries.last().map(|e| e.id.clone());
+            }
+            for entry in entries {
+                match DomainEvent::from_stream_fields(&entry.map) {
+                    Ok(event) => self.buf.push_back(BusMessage {
+                        id: entry.id,
+                        event,
+                    }),
+                    // Never decodable, so redelivering it would only block the group.
+                    Err(e) => {
+                        log::error!("bus {} {}: dropping {}", self.topic, entry.id, e);
+                        self.redis.ack(&self.topic, &self.group, &entry.id).await?;
+                    }
+                }
+            }
+        }
+        Ok(self.buf.pop_front())
+    }
+
+    async fn ack(&mut self, id: &str) -> Result<()> {
+        self.redis.ack(&self.topic, &self.group, id).await
+    }
+}
+
+type GroupReceiver = Arc<tokio::sync::Mutex<broadcast::Receiver<BusMessage>>>;
+
+/// In-process bus over `tokio::sync::broadcast`. Nothing is persisted and
+/// `ack` is a no-op; a group that falls more than `capacity` events behind
+/// loses the oldest ones.
+pub struct MemoryBus {
+    capacity: usize,
+    seq: AtomicU64,
+    topics: Mutex<HashMap<String, broadcast::Sender<BusMessage>>>,
+    /// Consumers of one group share its receiver, so each event reaches one of them.
+    groups: Mutex<HashMap<(String, String), GroupReceiver>>,
+}
+
+impl MemoryBus {
+    pub fn new(capacity: usize) -> Self {
+        MemoryBus {
+            capacity,
+            seq: AtomicU64::new(0),
+            topics: Mutex::new(HashMap::new()),
+            groups: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn sender(&self, topic: &str) -> broadcast::Sender<BusMessage> {
+        self.topics
+            .lock()
+            .unwrap()
+            .entry(topic.to_string())
+            .or_insert_with(|| broadcast::channel(self.capacity).0)
+            .clone()
+    }
+}
+
+impl Default for MemoryBus {
+    fn default() -> Self {
+        MemoryBus::new(4096)
+    }
+}
+
+#[async_trait]
+impl EventBus for MemoryBus {
+    async fn publish(&self, topic: &str, event: &DomainEvent) -> Result<String> {
+        let id = (self.seq.fetch_add(1, Ordering::Relaxed) + 1).to_string();
+        // No receiver just means no group subscribed yet.
+        let _ = self.sender(topic).send(BusMessage {
+            id: id.clone(),
+            event: event.clone(),
+        });
+        Ok(id)
+    }
+
+    async fn subscribe(
+        &self,
+        topic: &str,
+        group: &str,
+        _consumer: &str,
+    ) -> Result<Box<dyn Subscription>> {
+        let sender = self.sender(topic);
+        let rx = self
+            .groups
+            .lock()
+            .unwrap()
+            .entry((topic.to_string(), group.to_string()))
+            .or_insert_with(|| Arc::new(tokio::sync::Mutex::new(sender.subscribe())))
+            .clone();
+        Ok(Box::new(MemorySubscription {
+            topic: topic.to_string(),
+            rx,
+        }))
+    }
+}
+
+struct MemorySubscription {
+    topic: String,
+    rx: GroupReceiver,
+}
+
+#[async_trait]
+impl Subscription for MemorySubscription {
+    async fn next(&mut self) -> Result<Option<BusMessage>> {
+        let mut rx = self.rx.lock().await;
+        loop {
+            match rx.recv().await {
+                Ok(msg) => return Ok(Some(msg)),
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    log::warn!("bus {}: lagged, {} events lost", self.topic, n)
+                }
+                Err(broadcast::error::RecvError::Closed) => return Ok(None),
+            }
+        }
+    }
+
+    async fn ack(&mut self, _id: &str) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::KlineEvent;
+
+    fn kline(id: i64) -> DomainEvent {
+        DomainEvent::Kline(KlineEvent {
+            id,
+            ..Default::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn test_memory_bus() {
+        let bus = MemoryBus::new(16);
+        let mut a1 = bus.subscribe("kline", "a", "1").await.unwrap();
+        let mut a2 = bus.subscribe("kline", "a", "2").await.unwrap();
+        let mut b = bus.subscribe("kline", "b", "1").await.unwrap();
+        bus.publish("kline", &kline(1)).await.unwrap();
+        bus.publish("kline", &kline(2)).await.unwrap();
+        bus.publish("other", &kline(3)).await.unwrap();
+
+        // Group `a` splits the events, group `b` sees all of them.
+        let first = a1.next().await.unwrap().unwrap();
+        let second = a2.next().await.unwrap().unwrap();
+        assert_eq!(first.event, kline(1));
+        assert_eq!(second.event, kline(2));
+        a1.ack(&first.id).await.unwrap();
+        assert_eq!(b.next().await.unwrap().unwrap().event, kline(1));
+        assert_eq!(b.next().await.unwrap().unwrap().event, kline(2));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a local redis server"]
+    async fn test_redis_redelivers_unacked() {
+        let redis = RedisQuery::new().unwrap();
+        let topic = format!("test_bus_{}", std::process::id());
+        let mut sub = redis.subscribe(&topic, "g", "c1").await.unwrap();
+        redis.publish(&topic, &kline(1)).await.unwrap();
+        redis.publish(&topic, &kline(2)).await.unwrap();
+        let first = sub.next().await.unwrap().unwrap();
+        sub.ack(&first.id).await.unwrap();
+        let second = sub.next().await.unwrap().unwrap();
+        drop(sub);
+
+        // a restarted consumer gets its unacked entry back before new ones
+        let mut sub = redis.subscribe(&topic, "g", "c1").await.unwrap();
+        redis.publish(&topic, &kline(3)).await.unwrap();
+        assert_eq!(sub.next().await.unwrap().unwrap(), second);
+        sub.ack(&second.id).await.unwrap();
+        assert_eq!(sub.next().await.unwrap().unwrap().event, kline(3));
+
+        let mut conn = redis.db.get_async_connection().await.unwrap();
+        let _: i64 = conn.del(&topic).await.unwrap();
+    }
+}
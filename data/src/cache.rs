@@ -1,44 +1,171 @@
-use std::collections::BTreeMap;
+use std::collections::HashMap;
 use std::fmt::format;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
 
+use crate::book::{DepthOutcome, DepthSnapshot, DepthUpdate, Level, OrderBook};
+use crate::codec::DomainEvent;
 use crate::domain::KlineEvent;
+use async_trait::async_trait;
+use chrono::Utc;
+use futures_util::FutureExt;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{oneshot, watch};
+use tokio_graceful::ShutdownGuard;
 use api::keys::{key_depth_ask_h, key_depth_ask_z, key_depth_bid_h, key_depth_bid_z};
-use ecode::Result;
+use ecode::{Reason, Result, Status};
 use redis::aio::PubSub;
-use redis::streams::StreamReadOptions;
-use redis::streams::StreamReadReply;
+use redis::streams::{
+    StreamClaimReply, StreamId, StreamPendingCountReply, StreamPendingId, StreamPendingReply,
+    StreamRangeReply, StreamReadOptions, StreamReadReply,
+};
 use redis::AsyncCommands;
 use rust_decimal::Decimal;
 
-const ASK_SCRIPT1: &str = r"local zkey = KEYS[1]
-local hkey = KEYS[2]
-local priceList = redis.call('ZRANGE', zkey, 0, 3)
-local ret = {}
-if #priceList > 0 then
-    for _, v in ipairs(priceList) do
-        local quantity = redis.call('HGET', hkey, v)
-        local it = {v, quantity}
-        table.insert(ret, it)
+/// Loads both sides of a book in one call so they come from the same moment.
+/// KEYS: ask zset, ask hash, bid zset, bid hash. ARGV[1]: last rank to read
+/// (`depth - 1`, or -1 for the whole side). Returns `{asks, bids}`, each a
+/// flat `price, qty, ...` list, best price first.
+const BOOK_SCRIPT: &str = r"local stop = tonumber(ARGV[1])
+local function side(zkey, hkey, prices)
+    local ret = {}
+    for _, p in ipairs(prices) do
+        local qty = redis.call('HGET', hkey, p)
+        if qty then
+            ret[#ret + 1] = p
+            ret[#ret + 1] = qty
+        end
     end
+    return ret
 end
-return ret";
-
-const BID_SCRIPT1: &str = r"local zkey = KEYS[1]
-local hkey = KEYS[2]
-local priceList = redis.call('ZRANGE', zkey, 0, 3, 'REV')
-local ret = {}
-if #priceList > 0 then
-    for _, v in ipairs(priceList) do
-        local quantity = redis.call('HGET', hkey, v)
-        local it = {v, quantity}
-        table.insert(ret, it)
+return {
+    side(KEYS[1], KEYS[2], redis.call('ZRANGE', KEYS[1], 0, stop)),
+    side(KEYS[3], KEYS[4], redis.call('ZREVRANGE', KEYS[3], 0, stop)),
+}";
+
+fn parse_levels(key: &str, flat: Vec<String>) -> Result<Vec<Level>> {
+    flat.chunks_exact(2)
+        .map(|pair| {
+            let parse = |v: &str| {
+                Decimal::from_str(v).map_err(|_| {
+                    Status::new(
+                        500,
+                        Reason::InvalidDecimal,
+                        format!("{}: {:?} is not a decimal", key, v),
+                    )
+                })
+            };
+            Ok(Level {
+                price: parse(&pair[0])?,
+                qty: parse(&pair[1])?,
+            })
+        })
+        .collect()
+}
+
+/// Replaces a whole book. KEYS: ask zset, ask hash, bid zset, bid hash, seq
+/// hash. ARGV: last update id, ask level count, then `price, qty` pairs of the
+/// asks followed by the bids.
+const SNAPSHOT_SCRIPT: &str = r"redis.call('DEL', KEYS[1], KEYS[2], KEYS[3], KEYS[4])
+local function put(zkey, hkey, price, qty)
+    redis.call('ZADD', zkey, price, price)
+    redis.call('HSET', hkey, price, qty)
+end
+local asks_end = 3 + 2 * tonumber(ARGV[2])
+for i = 3, #ARGV, 2 do
+    if i < asks_end then
+        put(KEYS[1], KEYS[2], ARGV[i], ARGV[i + 1])
+    else
+        put(KEYS[3], KEYS[4], ARGV[i], ARGV[i + 1])
     end
 end
-return ret";
+redis.call('HSET', KEYS[5], 'id', ARGV[1], 'synced', 0)
+return 1";
 
-#[derive(Debug)]
+/// Applies one diff if it continues the stored book. KEYS as `SNAPSHOT_SCRIPT`.
+/// ARGV: first update id, final update id, previous final update id or '',
+/// ask level count, then the level pairs. Update ids stay well below 2^53, so
+/// Lua numbers hold them exactly.
+const DIFF_SCRIPT: &str = r"local last = redis.call('HGET', KEYS[5], 'id')
+if not last then
+    return 'resync'
+end
+last = tonumber(last)
+local first, final = tonumber(ARGV[1]), tonumber(ARGV[2])
+if final <= last then
+    return 'stale'
+end
+local ok
+if redis.call('HGET', KEYS[5], 'synced') == '1' then
+    if ARGV[3] ~= '' then
+        ok = tonumber(ARGV[3]) == last
+    else
+        ok = first == last + 1
+    end
+else
+    ok = first <= last + 1
+end
+if not ok then
+    return 'resync'
+end
+local function set(zkey, hkey, price, qty)
+    if tonumber(qty) == 0 then
+        redis.call('ZREM', zkey, price)
+        redis.call('HDEL', hkey, price)
+    else
+        redis.call('ZADD', zkey, price, price)
+        redis.call('HSET', hkey, price, qty)
+    end
+end
+local asks_end = 5 + 2 * tonumber(ARGV[4])
+for i = 5, #ARGV, 2 do
+    if i < asks_end then
+        set(KEYS[1], KEYS[2], ARGV[i], ARGV[i + 1])
+    else
+        set(KEYS[3], KEYS[4], ARGV[i], ARGV[i + 1])
+    end
+end
+redis.call('HSET', KEYS[5], 'id', ARGV[2], 'synced', 1)
+return 'ok'";
+
+/// Last applied update id of a book, next to its depth keys.
+fn key_depth_seq(market: &str, symbol: &str) -> String {
+    format!("{}:seq", key_depth_ask_z(market, symbol))
+}
+
+/// Depth keys of a book in script `KEYS` order.
+fn depth_keys(market: &str, symbol: &str) -> [String; 5] {
+    let s = symbol.to_uppercase();
+    [
+        key_depth_ask_z(market, &s),
+        key_depth_ask_h(market, &s),
+        key_depth_bid_z(market, &s),
+        key_depth_bid_h(market, &s),
+        key_depth_seq(market, &s),
+    ]
+}
+
+/// Ask count followed by `price, qty` pairs of the asks then the bids. Prices
+/// are normalized so `100.10` and `100.1` name the same level.
+fn level_args(asks: &[Level], bids: &[Level], skip_empty: bool) -> Vec<String> {
+    let keep = |l: &&Level| !(skip_empty && l.qty.is_zero());
+    let asks: Vec<&Level> = asks.iter().filter(keep).collect();
+    let mut args = vec![asks.len().to_string()];
+    for l in asks.into_iter().chain(bids.iter().filter(keep)) {
+        args.push(l.price.normalize().to_string());
+        args.push(l.qty.to_string());
+    }
+    args
+}
+
+/// Stream holding events rejected by `ingest`, capped at about `DEAD_LETTER_MAXLEN` entries.
+pub const KEY_DEAD_LETTER: &str = "data:dead_letter";
+const DEAD_LETTER_MAXLEN: usize = 100_000;
+const FORCE_ORDER_STREAM_LEN: usize = 100;
+
+#[derive(Debug, Clone)]
 pub struct RedisQuery {
-    db: redis::Client,
+    pub(crate) db: redis::Client,
 }
 
 impl RedisQuery {
@@ -47,49 +174,88 @@ impl RedisQuery {
         Ok(RedisQuery { db: client })
     }
 
-    pub async fn range_ask(&mut self, market: &str, symbol: &str) -> Result<Vec<Vec<Decimal>>> {
+    /// Best `depth` levels of each side, the whole book when `depth` is 0.
+    pub async fn order_book(&self, market: &str, symbol: &str, depth: usize) -> Result<OrderBook> {
         let s = symbol.to_uppercase();
-        let zkey = key_depth_ask_z(market, &s);
-        let hkey = key_depth_ask_h(market, &s);
-
-        let script = redis::Script::new(ASK_SCRIPT1);
-        let result: Vec<Vec<f64>> = script.key(zkey).key(hkey).invoke(&mut self.db)?;
-        let mut list: Vec<Vec<Decimal>> = vec![];
-        for it in result {
-            let first = it[0];
-            let seconde = it[1];
-            let new_it = vec![
-                Decimal::from_f64_retain(first).unwrap(),
-                Decimal::from_f64_retain(seconde).unwrap(),
-            ];
-            list.push(new_it);
-        }
+        let ask_z = key_depth_ask_z(market, &s);
+        let bid_z = key_depth_bid_z(market, &s);
+        let stop = if depth == 0 { -1 } else { depth as i64 - 1 };
+        let mut conn = self.db.get_async_connection().await?;
+        let (asks, bids): (Vec<String>, Vec<String>) = redis::Script::new(BOOK_SCRIPT)
+            .key(&ask_z)
+            .key(key_depth_ask_h(market, &s))
+            .key(&bid_z)
+            .key(key_depth_bid_h(market, &s))
+            .arg(stop)
+            .invoke_async(&mut conn)
+            .await?;
+        Ok(OrderBook {
+            bids: parse_levels(&bid_z, bids)?,
+            asks: parse_levels(&ask_z, asks)?,
+        })
+    }
 
-        // tracing::info!("zrange {:?}", result);
-        Ok(list)
+    /// Top four asks as `[price, qty]` pairs, see `order_book`.
+    pub async fn range_ask(&mut self, market: &str, symbol: &str) -> Result<Vec<Vec<Decimal>>> {
+        let book = self.order_book(market, symbol, 4).await?;
+        Ok(book.asks.into_iter().map(|l| vec![l.price, l.qty]).collect())
     }
 
+    /// Top four bids as `[price, qty]` pairs, see `order_book`.
     pub async fn range_bid(&mut self, market: &str, symbol: &str) -> Result<Vec<Vec<Decimal>>> {
-        let s = symbol.to_uppercase();
-        let zkey = key_depth_bid_z(market, &s);
-        let hkey = key_depth_bid_h(market, &s);
-
-        let script = redis::Script::new(BID_SCRIPT1);
-        let result: Vec<Vec<f64>> = script.key(zkey).key(hkey).invoke(&mut self.db)?;
+        let book = self.order_book(market, symbol, 4).await?;
+        Ok(book.bids.into_iter().map(|l| vec![l.price, l.qty]).collect())
+    }
 
-        // tracing::info!("zrange {:?}", result);
+    /// Replaces the stored book of `symbol` with `snapshot`.
+    pub async fn apply_depth_snapshot(
+        &self,
+        market: &str,
+        symbol: &str,
+        snapshot: &DepthSnapshot,
+    ) -> Result<()> {
+        let script = redis::Script::new(SNAPSHOT_SCRIPT);
+        let mut invocation = script.prepare_invoke();
+        for key in depth_keys(market, symbol) {
+            invocation.key(key);
+        }
+        invocation.arg(snapshot.last_update_id);
+        invocation.arg(level_args(&snapshot.asks, &snapshot.bids, true));
+        let mut conn = self.db.get_async_connection().await?;
+        let _: i64 = invocation.invoke_async(&mut conn).await?;
+        Ok(())
+    }
 
-        let mut list: Vec<Vec<Decimal>> = vec![];
-        for it in result {
-            let first = it[0];
-            let seconde = it[1];
-            let new_it = vec![
-                Decimal::from_f64_retain(first).unwrap(),
-                Decimal::from_f64_retain(seconde).unwrap(),
-            ];
-            list.push(new_it);
+    /// Applies `update` if it continues the stored book, atomically with the
+    /// sequence check.
+    pub async fn apply_depth_update(
+        &self,
+        market: &str,
+        symbol: &str,
+        update: &DepthUpdate,
+    ) -> Result<DepthOutcome> {
+        let script = redis::Script::new(DIFF_SCRIPT);
+        let mut invocation = script.prepare_invoke();
+        for key in depth_keys(market, symbol) {
+            invocation.key(key);
         }
-        Ok(list)
+        invocation
+            .arg(update.first_update_id)
+            .arg(update.final_update_id)
+            .arg(
+                update
+                    .prev_final_update_id
+                    .map(|v| v.to_string())
+                    .unwrap_or_default(),
+            )
+            .arg(level_args(&update.asks, &update.bids, false));
+        let mut conn = self.db.get_async_connection().await?;
+        let res: String = invocation.invoke_async(&mut conn).await?;
+        Ok(match res.as_str() {
+            "ok" => DepthOutcome::Applied,
+            "stale" => DepthOutcome::Stale,
+            _ => DepthOutcome::Resync,
+        })
     }
 
     pub async fn into_pubsub(&self) -> Result<PubSub> {
@@ -98,46 +264,46 @@ impl RedisQuery {
     }
 
     pub async fn xadd_kline(&self, key: &str, kline: KlineEvent) -> Result<()> {
+        self.xadd_event(key, &DomainEvent::Kline(kline)).await
+    }
+
+    /// Appends `event` in its versioned stream form, see `DomainEvent::to_stream_fields`.
+    pub async fn xadd_event(&self, key: &str, event: &DomainEvent) -> Result<()> {
         let mut conn = self.db.get_async_connection().await?;
-        let mut map: BTreeMap<&str, String> = BTreeMap::new();
-        map.insert("id", format!("{}", kline.id));
-        map.insert("event", kline.event);
-        conn.xadd_map(&[key], &["*"], map).await?;
+        conn.xadd(key, "*", &event.to_stream_fields()?).await?;
         Ok(())
     }
 
-    pub async fn xadd_force_order(&self, kline: api::event::EventForceOrder) -> Result<()> {
-        let key = api::keys::key_ta_force_order(&kline.exchange, &kline.market, &kline.symbol);
+    /// Appends the tally to its TA stream in the versioned stream form. The
+    /// stream only feeds live indicators, so it is trimmed to about the last
+    /// `FORCE_ORDER_STREAM_LEN` entries.
+    pub async fn xadd_force_order(&self, order: api::event::EventForceOrder) -> Result<()> {
+        let key = api::keys::key_ta_force_order(&order.exchange, &order.market, &order.symbol);
+        let event = DomainEvent::ForceOrderTally((&order).try_into()?);
         let mut conn = self.db.get_async_connection().await?;
-        let mut map: BTreeMap<&str, String> = BTreeMap::new();
-        map.insert("exchange", format!("{}", kline.exchange));
-        map.insert("market", kline.market);
-        map.insert("symbol", kline.symbol);
-        map.insert("base", kline.base);
-        map.insert("quote", kline.quote);
-        map.insert(
-            "total_buy_quantity",
-            format!("{}", kline.total_buy_quantity),
-        );
-        map.insert(
-            "total_sell_quantity",
-            format!("{}", kline.total_sell_quantity),
-        );
-        conn.xadd_maxlen_map(
-            &[key],
-            redis::streams::StreamMaxlen::Approx(100),
-            &["*"],
-            map,
+        conn.xadd_maxlen(
+            key,
+            redis::streams::StreamMaxlen::Approx(FORCE_ORDER_STREAM_LEN),
+            "*",
+            &event.to_stream_fields()?,
         )
-            .await?;
+        .await?;
         Ok(())
     }
 
     pub async fn mkgroup(&self, key: &str) -> Result<()> {
+        self.create_group(key, "group-1", "$").await
+    }
+
+    /// Creates `group` on `key` reading from `start`, creating the stream too.
+    /// An existing group is left as is.
+    pub async fn create_group(&self, key: &str, group: &str, start: &str) -> Result<()> {
         let mut conn = self.db.get_async_connection().await?;
-        conn.xgroup_create_mkstream(&[key], &["group-1"], "$")
-            .await?;
-        Ok(())
+        let res: redis::RedisResult<()> = conn.xgroup_create_mkstream(key, group, start).await;
+        match res {
+            Err(e) if e.code() == Some("BUSYGROUP") => Ok(()),
+            res => Ok(res?),
+        }
     }
 
     pub async fn into_stream(&self, key: &str) -> Result<StreamReadReply> {
@@ -152,8 +318,729 @@ impl RedisQuery {
     }
 
     pub async fn xack(&self, key: &str, id: String) -> Result<()> {
+        self.ack(key, "group-1", &id).await
+    }
+
+    pub async fn ack(&self, key: &str, group: &str, id: &str) -> Result<()> {
+        let mut conn = self.db.get_async_connection().await?;
+        let _: i64 = conn.xack(key, group, &[id]).await?;
+        Ok(())
+    }
+}
+
+/// Fetches full books when a `DepthMaintainer` has to resync.
+#[async_trait]
+pub trait DepthSnapshotSource {
+    async fn depth_snapshot(&self, market: &str, symbol: &str) -> Result<DepthSnapshot>;
+}
+
+/// Snapshots fetched in a row before `DepthMaintainer` gives up on an update.
+const MAX_RESYNC: usize = 3;
+
+/// Sole writer of one symbol's depth keys. Feed it every diff of the exchange
+/// stream in order; it resyncs from `source` whenever the sequence breaks.
+pub struct DepthMaintainer<S> {
+    redis: RedisQuery,
+    source: S,
+    market: String,
+    symbol: String,
+}
+
+impl<S: DepthSnapshotSource + Sync> DepthMaintainer<S> {
+    pub fn new(redis: RedisQuery, source: S, market: &str, symbol: &str) -> Self {
+        DepthMaintainer {
+            redis,
+            source,
+            market: market.to_string(),
+            symbol: symbol.to_string(),
+        }
+    }
+
+    pub async fn on_update(&self, update: &DepthUpdate) -> Result<DepthOutcome> {
+        let mut outcome = self
+            .redis
+            .apply_depth_update(&self.market, &self.symbol, update)
+            .await?;
+        for _ in 0..MAX_RESYNC {
+            if outcome != DepthOutcome::Resync {
+                return Ok(outcome);
+            }
+            log::warn!(
+                "depth {} {} out of sync at {}, resyncing",
+                self.market,
+                self.symbol,
+                update.first_update_id
+            );
+            let snapshot = self.source.depth_snapshot(&self.market, &self.symbol).await?;
+            self.redis
+                .apply_depth_snapshot(&self.market, &self.symbol, &snapshot)
+                .await?;
+            outcome = self
+                .redis
+                .apply_depth_update(&self.market, &self.symbol, update)
+                .await?;
+        }
+        if outcome == DepthOutcome::Resync {
+            return Err(Status::new(
+                500,
+                Reason::DepthOutOfSync,
+                format!(
+                    "depth {} {}: no snapshot covers update {}",
+                    self.market, self.symbol, update.first_update_id
+                ),
+            ));
+        }
+        Ok(outcome)
+    }
+}
+
+fn default_block_ms() -> usize {
+    100
+}
+
+fn default_count() -> usize {
+    200
+}
+
+fn default_claim_idle_ms() -> usize {
+    60_000
+}
+
+fn default_max_deliveries() -> usize {
+    5
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConsumerConf {
+    pub stream: String,
+    pub group: String,
+    /// Unique per process, e.g. the host name.
+    pub consumer: String,
+    #[serde(default = "default_block_ms")]
+    pub block_ms: usize,
+    /// Entries read per round.
+    #[serde(default = "default_count")]
+    pub count: usize,
+    /// Pending entries idle this long belong to a dead consumer and are claimed.
+    #[serde(default = "default_claim_idle_ms")]
+    pub claim_idle_ms: usize,
+    /// Deliveries after which a failing entry is moved to `KEY_DEAD_LETTER`.
+    #[serde(default = "default_max_deliveries")]
+    pub max_deliveries: usize,
+}
+
+/// Handles one stream entry. Returning `Ok` acks it; an error leaves it
+/// pending to be retried after `claim_idle_ms`.
+#[async_trait]
+pub trait StreamHandler {
+    async fn handle(&self, entry: &StreamId) -> Result<()>;
+}
+
+/// At-least-once consumer of one stream through a consumer group.
+pub struct StreamConsumer<H> {
+    redis: RedisQuery,
+    conf: ConsumerConf,
+    handler: H,
+}
+
+impl<H: StreamHandler + Sync> StreamConsumer<H> {
+    pub fn new(redis: RedisQuery, conf: ConsumerConf, handler: H) -> Self {
+        StreamConsumer {
+            redis,
+            conf,
+            handler,
+        }
+    }
+
+    /// Creates the group at the start of the stream if it does not exist.
+    pub async fn ensure_group(&self) -> Result<()> {
+        self.redis.create_group(&self.conf.stream, &self.conf.group, "0").await
+    }
+
+    /// Reads and handles one batch of new entries. Returns how many were acked.
+    pub async fn poll(&self) -> Result<usize> {
+        let opts = StreamReadOptions::default()
+            .group(&self.conf.group, &self.conf.consumer)
+            .block(self.conf.block_ms)
+            .count(self.conf.count);
+        let reply: Option<StreamReadReply> = {
+            let mut conn = self.redis.db.get_async_connection().await?;
+            conn.xread_options(&[&self.conf.stream], &[">"], &opts).await?
+        };
+        let ids = reply
+            .into_iter()
+            .flat_map(|r| r.keys)
+            .flat_map(|k| k.ids)
+            .collect();
+        self.handle_all(ids).await
+    }
+
+    /// Dead-letters pending entries delivered `max_deliveries` times, then
+    /// claims and handles the rest of those idle for `claim_idle_ms`.
+    pub async fn recover(&self) -> Result<usize> {
+        let c = &self.conf;
+        let mut conn = self.redis.db.get_async_connection().await?;
+        let pending: StreamPendingCountReply =
+            conn.xpending_count(&c.stream, &c.group, "-", "+", c.count).await?;
+        for p in pending.ids.iter() {
+            if p.last_delivered_ms >= c.claim_idle_ms && p.times_delivered >= c.max_deliveries {
+                self.dead_letter(p).await?;
+            }
+        }
+        let reply: redis::Value = redis::cmd("XAUTOCLAIM")
+            .arg(&c.stream)
+            .arg(&c.group)
+            .arg(&c.consumer)
+            .arg(c.claim_idle_ms)
+            .arg("0-0")
+            .arg("COUNT")
+            .arg(c.count)
+            .query_async(&mut conn)
+            .await?;
+        self.handle_all(parse_autoclaim(&reply)?).await
+    }
+
+    /// Polls until shutdown, running `recover` every `claim_idle_ms`. An entry
+    /// being handled at shutdown is left pending and redelivered later.
+    pub async fn run(&self, guard: ShutdownGuard) {
+        let every = Duration::from_millis(self.conf.claim_idle_ms as u64);
+        let mut last_recover: Option<Instant> = None;
+        loop {
+            if guard.cancelled().now_or_never().is_some() {
+                break;
+            }
+            let due = match last_recover {
+                Some(t) => t.elapsed() >= every,
+                None => true,
+            };
+            if due {
+                last_recover = Some(Instant::now());
+                let res = match self.ensure_group().await {
+                    Ok(()) => self.recover().await,
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = res {
+                    log::error!("recover {} {}: {}", self.conf.stream, self.conf.group, e);
+                }
+            }
+            tokio::select! {
+                _ = guard.cancelled() => break,
+                res = self.poll() => {
+                    if let Err(e) = res {
+                        log::error!("consume {} {}: {}", self.conf.stream, self.conf.group, e);
+                        tokio::time::sleep(Duration::from_secs(1)).await;
+                    }
+                }
+            }
+        }
+        log::info!("consumer {} {} stopping", self.conf.stream, self.conf.consumer);
+    }
+
+    async fn handle_all(&self, ids: Vec<StreamId>) -> Result<usize> {
+        let mut acked = 0;
+        for entry in ids.iter() {
+            match self.handler.handle(entry).await {
+                Ok(()) => {
+                    self.redis
+                        .ack(&self.conf.stream, &self.conf.group, &entry.id)
+                        .await?;
+                    acked += 1;
+                }
+                Err(e) => log::warn!("{} {} failed: {}", self.conf.stream, entry.id, e),
+            }
+        }
+        Ok(acked)
+    }
+
+    async fn dead_letter(&self, p: &StreamPendingId) -> Result<()> {
+        let c = &self.conf;
+        let mut conn = self.redis.db.get_async_connection().await?;
+        let range: StreamRangeReply = conn.xrange(&c.stream, &p.id, &p.id).await?;
+        let mut fields: Vec<(String, Vec<u8>)> = vec![];
+        if let Some(entry) = range.ids.first() {
+            for (k, v) in entry.map.iter() {
+                fields.push((k.clone(), redis::from_redis_value(v)?));
+            }
+        }
+        fields.push(("source".to_string(), c.stream.clone().into_bytes()));
+        fields.push(("source_id".to_string(), p.id.clone().into_bytes()));
+        fields.push((
+            "reason".to_string(),
+            format!("failed {} deliveries to {}", p.times_delivered, c.group).into_bytes(),
+        ));
+        log::warn!("dead letter {} {} after {} deliveries", c.stream, p.id, p.times_delivered);
+        conn.xadd_maxlen(
+            KEY_DEAD_LETTER,
+            redis::streams::StreamMaxlen::Approx(DEAD_LETTER_MAXLEN),
+            "*",
+            &fields,
+        )
+        .await?;
+        self.redis.ack(&c.stream, &c.group, &p.id).await
+    }
+}
+
+/// Entries of an `XAUTOCLAIM` reply: `[cursor, entries, deleted ids]`. Before
+/// Redis 7 deleted entries show up as nils in `entries`.
+fn parse_autoclaim(reply: &redis::Value) -> Result<Vec<StreamId>> {
+    let entries = match reply {
+        redis::Value::Bulk(items) => match items.get(1) {
+            Some(redis::Value::Bulk(entries)) => entries
+                .iter()
+                .filter(|e| !matches!(e, redis::Value::Nil))
+                .cloned()
+                .collect(),
+            _ => vec![],
+        },
+        _ => vec![],
+    };
+    let reply: StreamClaimReply = redis::from_redis_value(&redis::Value::Bulk(entries))?;
+    Ok(reply.ids)
+}
+
+/// How much of a stream to keep. Trimming is approximate (`~`), so Redis may
+/// keep a few more entries than asked for.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Retention {
+    /// `XTRIM MAXLEN`: keep about this many newest entries.
+    MaxLen(usize),
+    /// `XTRIM MINID`: keep entries added in the last this many seconds.
+    MaxAgeSecs(u64),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RetentionRule {
+    /// Key glob as understood by `SCAN MATCH`, e.g. `kline:*`.
+    pub pattern: String,
+    pub retention: Retention,
+}
+
+fn default_trim_every_secs() -> u64 {
+    60
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RetentionConf {
+    /// Applied in order; a stream is trimmed by every rule it matches.
+    #[serde(default)]
+    pub rules: Vec<RetentionRule>,
+    #[serde(default = "default_trim_every_secs")]
+    pub every_secs: u64,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GroupStats {
+    pub name: String,
+    pub consumers: usize,
+    /// Delivered but not acked entries.
+    pub pending: usize,
+    /// Entries not yet delivered to the group. Needs Redis 7, `None` before
+    /// or when Redis cannot tell.
+    pub lag: Option<u64>,
+    pub last_delivered_id: String,
+    /// Age in ms of the oldest pending entry, by its id.
+    pub oldest_pending_ms: Option<i64>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StreamStats {
+    pub key: String,
+    pub length: usize,
+    pub groups: Vec<GroupStats>,
+}
+
+/// Milliseconds part of a stream id such as `1700000000000-0`.
+fn stream_id_ms(id: &str) -> Option<i64> {
+    id.split('-').next()?.parse().ok()
+}
+
+impl RedisQuery {
+    /// Trims `key` according to `retention`. Returns the number of entries removed.
+    pub async fn trim_stream(&self, key: &str, retention: &Retention) -> Result<usize> {
+        let mut cmd = redis::cmd("XTRIM");
+        cmd.arg(key);
+        match retention {
+            Retention::MaxLen(n) => cmd.arg("MAXLEN").arg("~").arg(*n),
+            Retention::MaxAgeSecs(secs) => {
+                let min = Utc::now().timestamp_millis() - *secs as i64 * 1000;
+                cmd.arg("MINID").arg("~").arg(format!("{}-0", min))
+            }
+        };
+        let mut conn = self.db.get_async_connection().await?;
+        Ok(cmd.query_async(&mut conn).await?)
+    }
+
+    /// Applies every rule of `conf` once. Returns the number of entries removed.
+    pub async fn trim_streams(&self, conf: &RetentionConf) -> Result<usize> {
+        let mut removed = 0;
+        for rule in conf.rules.iter() {
+            let keys: Vec<String> = {
+                let mut conn = self.db.get_async_connection().await?;
+                let mut iter: redis::AsyncIter<String> = redis::cmd("SCAN")
+                    .cursor_arg(0)
+                    .arg("MATCH")
+                    .arg(&rule.pattern)
+                    .arg("TYPE")
+                    .arg("stream")
+                    .clone()
+                    .iter_async(&mut conn)
+                    .await?;
+                let mut keys = vec![];
+                while let Some(key) = iter.next_item().await {
+                    keys.push(key);
+                }
+                keys
+            };
+            for key in keys {
+                removed += self.trim_stream(&key, &rule.retention).await?;
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Length of `key` and the backlog of each of its consumer groups.
+    pub async fn stream_stats(&self, key: &str) -> Result<StreamStats> {
+        let mut conn = self.db.get_async_connection().await?;
+        let length: usize = conn.xlen(key).await?;
+        let groups: Vec<HashMap<String, redis::Value>> =
+            redis::cmd("XINFO").arg("GROUPS").arg(key).query_async(&mut conn).await?;
+        let now = Utc::now().timestamp_millis();
+        let mut res = StreamStats {
+            key: key.to_string(),
+            length,
+            groups: Vec::with_capacity(groups.len()),
+        };
+        for g in groups {
+            let field = |name: &str| g.get(name).cloned().unwrap_or(redis::Value::Nil);
+            let name: String = redis::from_redis_value(&field("name"))?;
+            let pending: StreamPendingReply = conn.xpending(key, &name).await?;
+            let oldest_pending_ms = match &pending {
+                StreamPendingReply::Data(d) => stream_id_ms(&d.start_id).map(|ms| now - ms),
+                StreamPendingReply::Empty => None,
+            };
+            res.groups.push(GroupStats {
+                consumers: redis::from_redis_value(&field("consumers"))?,
+                pending: pending.count(),
+                lag: redis::from_redis_value(&field("lag"))?,
+                last_delivered_id: redis::from_redis_value(&field("last-delivered-id"))?,
+                oldest_pending_ms,
+                name,
+            });
+        }
+        Ok(res)
+    }
+}
+
+/// Runs `trim_streams` every `conf.every_secs` until shutdown.
+pub async fn run_trimmer(redis: RedisQuery, conf: RetentionConf, guard: ShutdownGuard) {
+    let mut ticker = tokio::time::interval(Duration::from_secs(conf.every_secs.max(1)));
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+    loop {
+        tokio::select! {
+            _ = guard.cancelled() => break,
+            _ = ticker.tick() => {
+                match redis.trim_streams(&conf).await {
+                    Ok(n) => log::debug!("trimmed {} stream entries", n),
+                    Err(e) => log::error!("trim streams: {}", e),
+                }
+            }
+        }
+    }
+    log::info!("stream trimmer stopping");
+}
+
+/// Takes `KEYS[1]` if free, storing a fresh value of the `KEYS[2]` counter as
+/// both the owner and the fencing token. ARGV[1]: ttl in ms. Returns the
+/// token, or nil when the lock is held.
+const LEASE_ACQUIRE_SCRIPT: &str = r"if redis.call('EXISTS', KEYS[1]) == 1 then
+    return false
+end
+local fence = redis.call('INCR', KEYS[2])
+redis.call('SET', KEYS[1], fence, 'PX', ARGV[1])
+return fence";
+
+/// Extends `KEYS[1]` to ARGV[2] ms if it is still owned by token ARGV[1].
+const LEASE_RENEW_SCRIPT: &str = r"if redis.call('GET', KEYS[1]) == ARGV[1] then
+    return redis.call('PEXPIRE', KEYS[1], ARGV[2])
+end
+return 0";
+
+/// Deletes `KEYS[1]` if it is still owned by token ARGV[1].
+const LEASE_RELEASE_SCRIPT: &str = r"if redis.call('GET', KEYS[1]) == ARGV[1] then
+    return redis.call('DEL', KEYS[1])
+end
+return 0";
+
+/// Poll interval of `acquire_lease`.
+const LEASE_RETRY: Duration = Duration::from_millis(100);
+
+fn key_lock(name: &str) -> String {
+    format!("lock:{}", name)
+}
+
+/// Never expires, so tokens keep increasing across holders.
+fn key_lock_fence(name: &str) -> String {
+    format!("lock:{}:fence", name)
+}
+
+/// A lock held until `ttl` after the last acquire or renewal. `fence` grows
+/// with every acquisition of `name`; writers pass it along so stores can
+/// reject a holder whose lease already expired.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lease {
+    pub name: String,
+    pub fence: i64,
+    pub ttl: Duration,
+}
+
+impl RedisQuery {
+    pub async fn try_acquire_lease(&self, name: &str, ttl: Duration) -> Result<Option<Lease>> {
         let mut conn = self.db.get_async_connection().await?;
-        conn.xack(&[key], &["group-1"], &[id]).await?;
+        let fence: Option<i64> = redis::Script::new(LEASE_ACQUIRE_SCRIPT)
+            .key(key_lock(name))
+            .key(key_lock_fence(name))
+            .arg(ttl.as_millis() as u64)
+            .invoke_async(&mut conn)
+            .await?;
+        Ok(fence.map(|fence| Lease {
+            name: name.to_string(),
+            fence,
+            ttl,
+        }))
+    }
+
+    /// Retries `try_acquire_lease` until it succeeds or `wait` has passed.
+    pub async fn acquire_lease(&self, name: &str, ttl: Duration, wait: Duration) -> Result<Lease> {
+        let deadline = Instant::now() + wait;
+        loop {
+            if let Some(lease) = self.try_acquire_lease(name, ttl).await? {
+                return Ok(lease);
+            }
+            if Instant::now() >= deadline {
+                return Err(Status::new(
+                    409,
+                    Reason::LockTimeout,
+                    format!("lock {} still held after {:?}", name, wait),
+                ));
+            }
+            tokio::time::sleep(LEASE_RETRY.min(deadline - Instant::now())).await;
+        }
+    }
+
+    /// False when the lease expired or was taken over.
+    pub async fn renew_lease(&self, lease: &Lease) -> Result<bool> {
+        let mut conn = self.db.get_async_connection().await?;
+        let renewed: i64 = redis::Script::new(LEASE_RENEW_SCRIPT)
+            .key(key_lock(&lease.name))
+            .arg(lease.fence)
+            .arg(lease.ttl.as_millis() as u64)
+            .invoke_async(&mut conn)
+            .await?;
+        Ok(renewed == 1)
+    }
+
+    /// Never deletes a lock taken over by someone else. False when the lease
+    /// was no longer held.
+    pub async fn release_lease(&self, lease: &Lease) -> Result<bool> {
+        let mut conn = self.db.get_async_connection().await?;
+        let released: i64 = redis::Script::new(LEASE_RELEASE_SCRIPT)
+            .key(key_lock(&lease.name))
+            .arg(lease.fence)
+            .invoke_async(&mut conn)
+            .await?;
+        Ok(released == 1)
+    }
+
+    /// Renews `lease` every third of its ttl in the background. Redis errors
+    /// are retried until a full ttl has passed since the last renewal, after
+    /// which the lease counts as lost. Dropping the handle stops renewing and
+    /// lets the lease expire.
+    pub fn keep_lease(&self, lease: Lease) -> HeldLease {
+        let (held_tx, held) = watch::channel(true);
+        let (stop, mut stopped) = oneshot::channel::<()>();
+        let redis = self.clone();
+        let renewed = lease.clone();
+        let task = tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(renewed.ttl / 3);
+            ticker.tick().await;
+            let mut last_renewal = Instant::now();
+            loop {
+                tokio::select! {
+                    _ = &mut stopped => return,
+                    _ = ticker.tick() => {}
+                }
+                match redis.renew_lease(&renewed).await {
+                    Ok(true) => last_renewal = Instant::now(),
+                    Ok(false) => break,
+                    Err(e) if last_renewal.elapsed() < renewed.ttl => {
+                        log::warn!("lock {}: renew: {}", renewed.name, e)
+                    }
+                    Err(e) => {
+                        log::error!("lock {}: renew: {}", renewed.name, e);
+                        break;
+                    }
+                }
+            }
+            log::warn!("lock {}: lease {} lost", renewed.name, renewed.fence);
+            let _ = held_tx.send(false);
+        });
+        HeldLease {
+            redis: self.clone(),
+            lease,
+            held,
+            stop: Some(stop),
+            task,
+        }
+    }
+}
+
+/// A lease with its renewal task, see `RedisQuery::keep_lease`.
+pub struct HeldLease {
+    redis: RedisQuery,
+    lease: Lease,
+    held: watch::Receiver<bool>,
+    stop: Option<oneshot::Sender<()>>,
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl HeldLease {
+    pub fn lease(&self) -> &Lease {
+        &self.lease
+    }
+
+    pub fn fence(&self) -> i64 {
+        self.lease.fence
+    }
+
+    pub fn is_held(&self) -> bool {
+        *self.held.borrow()
+    }
+
+    /// Resolves once the lease is lost.
+    pub async fn lost(&mut self) {
+        while *self.held.borrow_and_update() {
+            if self.held.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+
+    /// Stops renewing and releases the lock if it is still ours.
+    pub async fn release(mut self) -> Result<bool> {
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(());
+        }
+        let _ = (&mut self.task).await;
+        self.redis.release_lease(&self.lease).await
+    }
+}
+
+fn default_leader_ttl_ms() -> u64 {
+    10_000
+}
+
+fn default_leader_retry_ms() -> u64 {
+    1_000
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LeaderConf {
+    /// Lock name, e.g. `bot:42`; one leader is elected per name.
+    pub name: String,
+    #[serde(default = "default_leader_ttl_ms")]
+    pub ttl_ms: u64,
+    /// Pause between attempts while another replica leads.
+    #[serde(default = "default_leader_retry_ms")]
+    pub retry_ms: u64,
+}
+
+/// Current outcome of an election: the fencing token while this replica
+/// leads, `None` otherwise.
+#[derive(Debug, Clone)]
+pub struct Leadership {
+    rx: watch::Receiver<Option<i64>>,
+}
+
+impl Leadership {
+    pub fn fence(&self) -> Option<i64> {
+        *self.rx.borrow()
+    }
+
+    pub fn is_leader(&self) -> bool {
+        self.fence().is_some()
+    }
+
+    /// Waits for the next change; false once the election has stopped.
+    pub async fn changed(&mut self) -> bool {
+        self.rx.changed().await.is_ok()
+    }
+}
+
+/// Campaigns for `conf.name` until shutdown. Leadership is dropped (the
+/// returned `Leadership` reads `None`) before the lock is released, so no
+/// two replicas act as leader at once while both are healthy.
+pub fn elect(redis: RedisQuery, conf: LeaderConf, guard: ShutdownGuard) -> Leadership {
+    let (tx, rx) = watch::channel(None);
+    tokio::spawn(run_election(redis, conf, tx, guard));
+    Leadership { rx }
+}
+
+async fn run_election(
+    redis: RedisQuery,
+    conf: LeaderConf,
+    tx: watch::Sender<Option<i64>>,
+    guard: ShutdownGuard,
+) {
+    let ttl = Duration::from_millis(conf.ttl_ms);
+    let retry = Duration::from_millis(conf.retry_ms);
+    loop {
+        let acquired = tokio::select! {
+            _ = guard.cancelled() => break,
+            r = redis.try_acquire_lease(&conf.name, ttl) => r,
+        };
+        match acquired {
+            Ok(Some(lease)) => {
+                log::info!("leader {}: elected with fence {}", conf.name, lease.fence);
+                let mut held = redis.keep_lease(lease);
+                tx.send_replace(Some(held.fence()));
+                let shutdown = tokio::select! {
+                    _ = guard.cancelled() => true,
+                    _ = held.lost() => false,
+                };
+                tx.send_replace(None);
+                if shutdown {
+                    if let Err(e) = held.release().await {
+                        log::error!("leader {}: release: {}", conf.name, e);
+                    }
+                    break;
+                }
+            }
+            Ok(None) => {}
+            Err(e) => log::error!("leader {}: {}", conf.name, e),
+        }
+        tokio::select! {
+            _ = guard.cancelled() => break,
+            _ = tokio::time::sleep(retry) => {}
+        }
+    }
+    tx.send_replace(None);
+    log::info!("leader {}: election stopping", conf.name);
+}
+
+#[async_trait]
+impl crate::DeadLetterRepo for RedisQuery {
+    async fn dead_letter(&self, event: &DomainEvent, reason: &str) -> Result<()> {
+        let mut fields = event.to_stream_fields()?.to_vec();
+        fields.push(("reason", reason.to_string()));
+        let mut conn = self.db.get_async_connection().await?;
+        conn.xadd_maxlen(
+            KEY_DEAD_LETTER,
+            redis::streams::StreamMaxlen::Approx(DEAD_LETTER_MAXLEN),
+            "*",
+            &fields,
+        )
+        .await?;
         Ok(())
     }
 }
@@ -187,4 +1074,127 @@ mod tests {
         // print!("-------1 {:?}\n", result);
         // assert!(result.is_ok());
     }
+
+    #[test]
+    fn test_parse_levels() {
+        let levels = parse_levels("k", vec!["2000.10".into(), "0.001".into()]).unwrap();
+        assert_eq!(levels[0].price.to_string(), "2000.10");
+        assert_eq!(levels[0].qty.to_string(), "0.001");
+        assert!(parse_levels("k", vec!["1e".into(), "1".into()]).is_err());
+    }
+
+    #[test]
+    fn test_level_args() {
+        let asks = vec![Level {
+            price: "100.10".parse().unwrap(),
+            qty: "0".parse().unwrap(),
+        }];
+        let bids = vec![Level {
+            price: "99.5".parse().unwrap(),
+            qty: "2".parse().unwrap(),
+        }];
+        assert_eq!(level_args(&asks, &bids, false), ["1", "100.1", "0", "99.5", "2"]);
+        assert_eq!(level_args(&asks, &bids, true), ["0", "99.5", "2"]);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a local redis server"]
+    async fn test_depth_sequencing() {
+        let redis = RedisQuery::new().unwrap();
+        let level = |p: &str, q: &str| Level {
+            price: p.parse().unwrap(),
+            qty: q.parse().unwrap(),
+        };
+        let snapshot = DepthSnapshot {
+            last_update_id: 100,
+            bids: vec![level("99", "1")],
+            asks: vec![level("101", "1")],
+        };
+        redis.apply_depth_snapshot("test", "btcusdt", &snapshot).await.unwrap();
+        let mut update = DepthUpdate {
+            first_update_id: 95,
+            final_update_id: 105,
+            asks: vec![level("101", "0"), level("102", "3")],
+            ..Default::default()
+        };
+        let apply = |u: DepthUpdate| {
+            let redis = redis.clone();
+            async move { redis.apply_depth_update("test", "btcusdt", &u).await.unwrap() }
+        };
+        assert_eq!(apply(update.clone()).await, DepthOutcome::Applied);
+        assert_eq!(apply(update.clone()).await, DepthOutcome::Stale);
+        update.first_update_id = 107;
+        update.final_update_id = 110;
+        assert_eq!(apply(update).await, DepthOutcome::Resync);
+
+        let book = redis.order_book("test", "btcusdt", 0).await.unwrap();
+        assert_eq!(book.asks, vec![level("102", "3")]);
+        assert_eq!(book.bids, vec![level("99", "1")]);
+    }
+
+    #[test]
+    fn test_parse_autoclaim() {
+        use redis::Value::{Bulk, Data, Nil};
+        let entry = Bulk(vec![
+            Data(b"1-0".to_vec()),
+            Bulk(vec![Data(b"v".to_vec()), Data(b"1".to_vec())]),
+        ]);
+        let reply = Bulk(vec![Data(b"0-0".to_vec()), Bulk(vec![entry, Nil]), Bulk(vec![])]);
+        let ids = parse_autoclaim(&reply).unwrap();
+        assert_eq!(ids.len(), 1);
+        assert_eq!(ids[0].id, "1-0");
+        assert_eq!(ids[0].get::<String>("v").as_deref(), Some("1"));
+    }
+
+    #[test]
+    fn test_consumer_conf_defaults() {
+        let conf: ConsumerConf = serde_json::from_str(
+            r#"{"stream":"kline","group":"ta","consumer":"host-1"}"#,
+        )
+        .unwrap();
+        assert_eq!(conf.count, 200);
+        assert_eq!(conf.max_deliveries, 5);
+    }
+
+    #[test]
+    fn test_retention_conf() {
+        let conf: RetentionConf = serde_json::from_str(
+            r#"{"rules":[{"pattern":"kline:*","retention":{"max_age_secs":86400}},
+                {"pattern":"data:*","retention":{"max_len":1000}}]}"#,
+        )
+        .unwrap();
+        assert_eq!(conf.every_secs, 60);
+        assert_eq!(conf.rules[0].retention, Retention::MaxAgeSecs(86400));
+        assert_eq!(conf.rules[1].retention, Retention::MaxLen(1000));
+        assert_eq!(stream_id_ms("1700000000000-3"), Some(1700000000000));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a local redis server"]
+    async fn test_lease() {
+        let redis = RedisQuery::new().unwrap();
+        let ttl = Duration::from_millis(300);
+        let lease = redis.try_acquire_lease("test", ttl).await.unwrap().unwrap();
+        assert_eq!(redis.try_acquire_lease("test", ttl).await.unwrap(), None);
+        assert!(redis.renew_lease(&lease).await.unwrap());
+
+        let held = redis.keep_lease(lease.clone());
+        tokio::time::sleep(ttl * 2).await;
+        assert!(held.is_held());
+        assert!(held.release().await.unwrap());
+        assert!(!redis.renew_lease(&lease).await.unwrap());
+
+        let next = redis.acquire_lease("test", ttl, ttl).await.unwrap();
+        assert!(next.fence > lease.fence);
+        // A stale holder cannot release its successor's lock.
+        assert!(!redis.release_lease(&lease).await.unwrap());
+        assert!(redis.release_lease(&next).await.unwrap());
+    }
+
+    #[test]
+    fn test_leader_conf() {
+        let conf: LeaderConf = serde_json::from_str(r#"{"name":"bot:1"}"#).unwrap();
+        assert_eq!(conf.ttl_ms, 10_000);
+        assert_eq!(conf.retry_ms, 1_000);
+    }
 }
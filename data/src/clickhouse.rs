@@ -1,19 +1,29 @@
 use std::str::FromStr;
 use async_trait::async_trait;
 use chrono::{DateTime, TimeDelta, Utc};
-use clickhouse_rs::{row, types::Complex, Block, Pool};
+use clickhouse_rs::{
+    row,
+    types::{Row, Simple},
+    Block, Pool,
+};
 use futures_util::StreamExt;
 use rust_decimal::Decimal;
-use ecode::Result;
+use ecode::{Reason, Result, Status};
 
 use crate::{
-    domain::{ForceOrderEvent, KlineEvent, AggTrade},
+    domain::{interval_duration, AggTrade, ClosePrice, ForceOrderEvent, KlineEvent},
     entity::{cex_market, cex_market_symbol},
+    snapshot::{AssetSnapshot, EquityPoint, PositionSnapshot},
 };
 
+/// Precision and scale of the `Decimal(18, 8)` columns, see `schema`. 18 is
+/// also the widest decimal clickhouse-rs can carry.
+pub(crate) const CH_DECIMAL_PRECISION: u32 = 18;
+pub(crate) const CH_DECIMAL_SCALE: u32 = 8;
+
 #[derive(Debug)]
 pub struct ClickhouseQuery {
-    db: Pool,
+    pub(crate) db: Pool,
 }
 
 impl ClickhouseQuery {
@@ -32,11 +42,7 @@ impl crate::AggTradeRepo for ClickhouseQuery {
         symbol: &cex_market_symbol::Model,
         limit: u32,
     ) -> Result<Vec<AggTrade>> {
-        let table = format!(
-            "{}_{}_aggtrade_{}",
-            symbol.exchange, symbol.market, symbol.symbol
-        )
-            .to_ascii_lowercase();
+        let table = aggtrade_table(symbol);
         let sql = format!("SELECT * FROM {} order by id desc LIMIT 0,{}", table, limit);
         let mut client = self.db.get_handle().await?;
         let mut stream = client.query(sql).stream();
@@ -53,23 +59,101 @@ impl crate::AggTradeRepo for ClickhouseQuery {
         Ok(res)
     }
 
-    async fn fetch_all(&self, symbol: &str, interval: u32) -> Result<Block<Complex>> {
-        let mut sql = format!(
-            "SELECT close FROM kline_1m WHERE match(symbol,'{}')",
-            symbol
+    async fn fetch_close_series(
+        &self,
+        symbol: &cex_market_symbol::Model,
+        interval: TimeDelta,
+        start: &DateTime<Utc>,
+        to: &DateTime<Utc>,
+    ) -> Result<Vec<ClosePrice>> {
+        let base = interval_duration(&symbol.interval).ok_or_else(|| {
+            Status::new(
+                400,
+                Reason::InvalidInterval,
+                format!("unknown kline interval {}", symbol.interval),
+            )
+        })?;
+        let step = interval.num_milliseconds();
+        if step < base.num_milliseconds() || step % base.num_milliseconds() != 0 {
+            return Err(Status::new(
+                400,
+                Reason::InvalidInterval,
+                format!(
+                    "interval {}ms is not a multiple of {} for {}",
+                    step, symbol.interval, symbol.symbol
+                ),
+            ));
+        }
+        let sql = format!(
+            "SELECT id, close FROM {} WHERE id >= {} AND id < {} AND id % {} = 0 ORDER BY id",
+            kline_table(symbol),
+            start.timestamp_millis(),
+            to.timestamp_millis(),
+            step
         );
-        if symbol == "ETHUSDT" {
-            sql = format!(
-                "SELECT close FROM kline_ethusdt_1m WHERE id / 60000 % {} = 0",
-                interval * 60
-            );
+        let mut client = self.db.get_handle().await?;
+        let mut stream = client.query(sql).stream();
+        let mut res = vec![];
+        while let Some(row) = stream.next().await {
+            let row = row?;
+            res.push(ClosePrice {
+                id: row.get("id")?,
+                close: from_ch_decimal(row.get("close")?),
+            });
+        }
+        Ok(res)
+    }
+
+    async fn insert_agg_trades(&self, table: &str, trades: Vec<AggTrade>) -> Result<()> {
+        if trades.is_empty() {
+            return Ok(());
+        }
+        let mut block = Block::with_capacity(trades.len());
+        for t in trades {
+            block.push(row! {
+                id: t.agg_trade_id,
+                time: t.time,
+                event: t.event,
+                symbol: t.symbol,
+                agg_trade_id: t.agg_trade_id,
+                price: to_ch_decimal(t.price)?,
+                quantity: to_ch_decimal(t.quantity)?,
+                first_trade_id: t.first_trade_id,
+                last_trade_id: t.last_trade_id,
+                trade_time: t.trade_time,
+                maker: t.maker,
+            })?;
         }
         let mut client = self.db.get_handle().await?;
-        let block = client.query(sql).fetch_all().await?;
-        Ok(block)
+        Ok(client.insert(table, block).await?)
     }
 }
 
+/// Per-symbol kline table, e.g. `kline_ethusdt_1m`.
+pub(crate) fn kline_table(symbol: &cex_market_symbol::Model) -> String {
+    kline_table_name(&symbol.symbol, &symbol.interval)
+}
+
+pub(crate) fn kline_table_name(symbol: &str, interval: &str) -> String {
+    format!("kline_{}_{}", symbol, interval).to_ascii_lowercase()
+}
+
+pub(crate) fn aggtrade_table(symbol: &cex_market_symbol::Model) -> String {
+    aggtrade_table_name(&symbol.exchange, &symbol.market, &symbol.symbol)
+}
+
+pub(crate) fn aggtrade_table_name(exchange: &str, market: &str, symbol: &str) -> String {
+    format!("{}_{}_aggtrade_{}", exchange, market, symbol).to_ascii_lowercase()
+}
+
+pub(crate) fn liquidation_table(symbol: &cex_market_symbol::Model) -> String {
+    liquidation_table_name(&symbol.exchange, &symbol.market, &symbol.symbol)
+}
+
+pub(crate) fn liquidation_table_name(exchange: &str, market: &str, symbol: &str) -> String {
+    format!("{}_{}_liquidation_order_{}", exchange, market, symbol).to_ascii_lowercase()
+}
+
 #[async_trait]
 impl crate::KlineRepo for ClickhouseQuery {
     async fn fetch_kline_limit(
@@ -81,30 +165,8 @@ impl crate::KlineRepo for ClickhouseQuery {
         let mut client = self.db.get_handle().await?;
         let mut stream = client.query(sql).stream();
         let mut res = vec![];
-        while let Some(Ok(row)) = stream.next().await {
-            let mut ev = KlineEvent::default();
-            ev.id = row.get("id").unwrap();
-            ev.event = row.get("event").unwrap();
-            ev.symbol = row.get("symbol").unwrap();
-            ev.start_time = row.get("start_time").unwrap();
-            ev.end_time = row.get("end_time").unwrap();
-            ev.interval = row.get("interval")?;
-            ev.first_trade_id = row.get("first_trade_id").unwrap();
-            ev.last_trade_id = row.get("last_trade_id").unwrap();
-            ev.open = row.get("open").unwrap();
-            ev.close = row.get("close").unwrap();
-            ev.high = row.get("high")?;
-            ev.low = row.get("low")?;
-            ev.volume = row.get("volume")?;
-            ev.trade_num = row.get("trade_num")?;
-            ev.quote_volume = row.get("quote_volume")?;
-            ev.active_buy_volume = row.get("active_buy_volume")?;
-            ev.active_buy_quote_volume = row.get("active_buy_volume")?;
-            ev.ema7 = row.get("ema7")?;
-            ev.ema25 = row.get("ema25")?;
-            ev.macd = row.get("macd")?;
-            ev.rsi = row.get("rsi")?;
-            res.push(ev);
+        while let Some(row) = stream.next().await {
+            res.push(kline_from_row(&row?)?);
         }
         Ok(res)
     }
@@ -119,30 +181,24 @@ impl crate::KlineRepo for ClickhouseQuery {
         let mut client = self.db.get_handle().await?;
         let mut stream = client.query(sql).stream();
         let mut res = vec![];
-        while let Some(Ok(row)) = stream.next().await {
-            let mut ev = KlineEvent::default();
-            ev.id = row.get("id").unwrap();
-            ev.event = row.get("event").unwrap();
-            ev.symbol = row.get("symbol").unwrap();
-            ev.start_time = row.get("start_time").unwrap();
-            ev.end_time = row.get("end_time").unwrap();
-            ev.interval = row.get("interval")?;
-            ev.first_trade_id = row.get("first_trade_id").unwrap();
-            ev.last_trade_id = row.get("last_trade_id").unwrap();
-            ev.open = row.get("open").unwrap();
-            ev.close = row.get("close").unwrap();
-            ev.high = row.get("high")?;
-            ev.low = row.get("low")?;
-            ev.volume = row.get("volume")?;
-            ev.trade_num = row.get("trade_num")?;
-            ev.quote_volume = row.get("quote_volume")?;
-            ev.active_buy_volume = row.get("active_buy_volume")?;
-            ev.active_buy_quote_volume = row.get("active_buy_volume")?;
-            ev.ema7 = row.get("ema7")?;
-            ev.ema25 = row.get("ema25")?;
-            ev.macd = row.get("macd")?;
-            ev.rsi = row.get("rsi")?;
-            res.push(ev);
+        while let Some(row) = stream.next().await {
+            res.push(kline_from_row(&row?)?);
+        }
+        Ok(res)
+    }
+
+    async fn fetch_kline_range(
+        &self,
+        table: &str,
+        start: &DateTime<Utc>,
+        to: &DateTime<Utc>,
+    ) -> Result<Vec<KlineEvent>> {
+        let sql = kline_range_sql(table, start, to);
+        let mut client = self.db.get_handle().await?;
+        let mut stream = client.query(sql).stream();
+        let mut res = vec![];
+        while let Some(row) = stream.next().await {
+            res.push(kline_from_row(&row?)?);
         }
         Ok(res)
     }
@@ -155,10 +211,8 @@ impl crate::KlineRepo for ClickhouseQuery {
     ) -> Result<Decimal> {
         let sql = format!("SELECT `open` FROM {} WHERE id >= {} AND id < {} ORDER BY id LIMIT 1; ", table, start.timestamp_millis(), to.timestamp_millis());
         let mut client = self.db.get_handle().await?;
-        let mut block = client.query(sql).fetch_all().await?;
-        let open: clickhouse_rs::types::Decimal = block.get(1, "open").unwrap();
-        let res = Decimal::from_str(open.to_string().as_str())?;
-        Ok(res)
+        let block = client.query(sql).fetch_all().await?;
+        Ok(from_ch_decimal(block.get(0, "open")?))
     }
 
     async fn fetch_kline_time_limit_high(
@@ -169,10 +223,8 @@ impl crate::KlineRepo for ClickhouseQuery {
     ) -> Result<Decimal> {
         let sql = format!("SELECT MAX(`high`) as high FROM {} WHERE id >= {} AND id < {} ORDER BY id LIMIT 1; ", table, start.timestamp_millis(), to.timestamp_millis());
         let mut client = self.db.get_handle().await?;
-        let mut block = client.query(sql).fetch_all().await?;
-        let open: clickhouse_rs::types::Decimal = block.get(1, "high").unwrap();
-        let res = Decimal::from_str(open.to_string().as_str())?;
-        Ok(res)
+        let block = client.query(sql).fetch_all().await?;
+        Ok(from_ch_decimal(block.get(0, "high")?))
     }
 
     async fn fetch_kline_time_limit_low(
@@ -183,10 +235,8 @@ impl crate::KlineRepo for ClickhouseQuery {
     ) -> Result<Decimal> {
         let sql = format!("SELECT MIN(`low`) as low FROM {} WHERE id >= {} AND id < {} ORDER BY id LIMIT 1; ", table, start.timestamp_millis(), to.timestamp_millis());
         let mut client = self.db.get_handle().await?;
-        let mut block = client.query(sql).fetch_all().await?;
-        let open: clickhouse_rs::types::Decimal = block.get(1, "low").unwrap();
-        let res = Decimal::from_str(open.to_string().as_str())?;
-        Ok(res)
+        let block = client.query(sql).fetch_all().await?;
+        Ok(from_ch_decimal(block.get(0, "low")?))
     }
 
     async fn fetch_kline_time_limit_close(
@@ -197,41 +247,105 @@ impl crate::KlineRepo for ClickhouseQuery {
     ) -> Result<Decimal> {
         let sql = format!("SELECT `close` FROM {} WHERE id >= {} AND id < {} ORDER BY DESC id LIMIT 1; ", table, start.timestamp_millis(), to.timestamp_millis());
         let mut client = self.db.get_handle().await?;
-        let mut block = client.query(sql).fetch_all().await?;
-        let open: clickhouse_rs::types::Decimal = block.get(1, "close").unwrap();
-        let res = Decimal::from_str(open.to_string().as_str())?;
-        Ok(res)
+        let block = client.query(sql).fetch_all().await?;
+        Ok(from_ch_decimal(block.get(0, "close")?))
     }
 
     async fn insert_kline(&self, table: &str, kline: KlineEvent) -> Result<()> {
         log::info!("{:?}", kline);
         let mut block = Block::new();
-        let _ = block.push(row! {
-            id: kline.id,
-            event: kline.event,
-            symbol: kline.symbol,
-            start_time: kline.start_time,
-            end_time: kline.end_time,
-            interval: kline.interval,
-            first_trade_id: kline.first_trade_id,
-            last_trade_id: kline.last_trade_id,
-            open: kline.open,
-            close: kline.close,
-            high: kline.high,
-            low: kline.low,
-            volume: kline.volume,
-            trade_num: kline.trade_num,
-            quote_volume: kline.quote_volume,
-            active_buy_volume: kline.active_buy_volume,
-            active_buy_quote_volume: kline.active_buy_quote_volume,
-            ema7: kline.ema7,
-            ema25: kline.ema25,
-            macd: kline.macd,
-            rsi: kline.rsi,
-        })?;
+        push_kline(&mut block, kline)?;
         let mut client = self.db.get_handle().await?;
         Ok(client.insert(table, block).await?)
     }
+
+    async fn insert_klines(&self, table: &str, klines: Vec<KlineEvent>) -> Result<()> {
+        if klines.is_empty() {
+            return Ok(());
+        }
+        let mut block = Block::with_capacity(klines.len());
+        for kline in klines {
+            push_kline(&mut block, kline)?;
+        }
+        let mut client = self.db.get_handle().await?;
+        Ok(client.insert(table, block).await?)
+    }
+
+    async fn delete_klines(&self, table: &str, start: i64, end: i64) -> Result<()> {
+        let sql = format!(
+            "ALTER TABLE {} DELETE WHERE id >= {} AND id < {} SETTINGS mutations_sync = 1",
+            table, start, end
+        );
+        let mut client = self.db.get_handle().await?;
+        client.execute(sql).await?;
+        self.rebuild_resampled(table, start, end).await
+    }
+}
+
+/// `FINAL` collapses the rows a re-run import wrote twice before any merge.
+fn kline_range_sql(table: &str, start: &DateTime<Utc>, to: &DateTime<Utc>) -> String {
+    format!(
+        "SELECT * FROM {} FINAL WHERE id >= {} AND id < {} ORDER BY id",
+        table,
+        start.timestamp_millis(),
+        to.timestamp_millis()
+    )
+}
+
+fn kline_from_row<'a>(row: &'a Row<'a, Simple>) -> Result<KlineEvent> {
+    Ok(KlineEvent {
+        id: row.get("id")?,
+        event: row.get("event")?,
+        symbol: row.get("symbol")?,
+        start_time: row.get("start_time")?,
+        end_time: row.get("end_time")?,
+        interval: row.get("interval")?,
+        first_trade_id: row.get("first_trade_id")?,
+        last_trade_id: row.get("last_trade_id")?,
+        open: from_ch_decimal(row.get("open")?),
+        close: from_ch_decimal(row.get("close")?),
+        high: from_ch_decimal(row.get("high")?),
+        low: from_ch_decimal(row.get("low")?),
+        volume: from_ch_decimal(row.get("volume")?),
+        trade_num: row.get("trade_num")?,
+        quote_volume: from_ch_decimal(row.get("quote_volume")?),
+        active_buy_volume: from_ch_decimal(row.get("active_buy_volume")?),
+        active_buy_quote_volume: from_ch_decimal(row.get("active_buy_quote_volume")?),
+        ema7: from_ch_decimal(row.get("ema7")?),
+        ema25: from_ch_decimal(row.get("ema25")?),
+        macd: from_ch_decimal(row.get("macd")?),
+        rsi: from_ch_decimal(row.get("rsi")?),
+        ..Default::default()
+    })
+}
+
+fn push_kline(block: &mut Block, kline: KlineEvent) -> Result<()> {
+    // Indicators are computed with rust_decimal's full scale, so they are
+    // rounded to the column scale rather than rejected.
+    block.push(row! {
+        id: kline.id,
+        event: kline.event,
+        symbol: kline.symbol,
+        start_time: kline.start_time,
+        end_time: kline.end_time,
+        interval: kline.interval,
+        first_trade_id: kline.first_trade_id,
+        last_trade_id: kline.last_trade_id,
+        open: to_ch_decimal(kline.open)?,
+        close: to_ch_decimal(kline.close)?,
+        high: to_ch_decimal(kline.high)?,
+        low: to_ch_decimal(kline.low)?,
+        volume: to_ch_decimal(kline.volume)?,
+        trade_num: kline.trade_num,
+        quote_volume: to_ch_decimal(kline.quote_volume)?,
+        active_buy_volume: to_ch_decimal(kline.active_buy_volume)?,
+        active_buy_quote_volume: to_ch_decimal(kline.active_buy_quote_volume)?,
+        ema7: to_ch_decimal(kline.ema7.round_dp(CH_DECIMAL_SCALE))?,
+        ema25: to_ch_decimal(kline.ema25.round_dp(CH_DECIMAL_SCALE))?,
+        macd: to_ch_decimal(kline.macd.round_dp(CH_DECIMAL_SCALE))?,
+        rsi: to_ch_decimal(kline.rsi.round_dp(CH_DECIMAL_SCALE))?,
+    })?;
+    Ok(())
 }
 
 #[async_trait]
@@ -242,12 +356,9 @@ impl crate::ForceOrderRepo for ClickhouseQuery {
         side: &str,
         start: &DateTime<Utc>,
     ) -> Result<Decimal> {
-        let table = format!(
-            "{}_{}_liquidation_order_{}",
-            symbol.exchange, symbol.market, symbol.symbol
-        ).to_ascii_lowercase();
+        let table = liquidation_table(symbol);
         let sql = format!(
-            "SELECT SUM(avg_value) as sum_value FROM {} WHERE id > {} AND side = '{}'",
+            "SELECT toString(SUM(avg_value)) as sum_value FROM {} WHERE id > {} AND side = '{}'",
             table, start.timestamp_micros(), side
         );
         let mut client = self.db.get_handle().await?;
@@ -256,6 +367,180 @@ impl crate::ForceOrderRepo for ClickhouseQuery {
         let v = Decimal::from_str(sum_value_s.as_str())?;
         Ok(v)
     }
+
+    async fn insert_force_orders(&self, table: &str, orders: Vec<ForceOrderEvent>) -> Result<()> {
+        if orders.is_empty() {
+            return Ok(());
+        }
+        let mut block = Block::with_capacity(orders.len());
+        for o in orders {
+            let avg_value = (o.avg_price * o.accumulated_filled_qty).round_dp(CH_DECIMAL_SCALE);
+            block.push(row! {
+                id: o.id,
+                exchange: o.exchange,
+                market: o.market,
+                event: o.event,
+                symbol: o.symbol,
+                side: o.side,
+                order_type: o.order_type,
+                time_in_force: o.time_in_force,
+                orig_quantity: to_ch_decimal(o.orig_quantity)?,
+                price: to_ch_decimal(o.price)?,
+                avg_price: to_ch_decimal(o.avg_price)?,
+                avg_value: to_ch_decimal(avg_value)?,
+                order_status: o.order_status,
+                last_filled_qty: to_ch_decimal(o.last_filled_qty)?,
+                accumulated_filled_qty: to_ch_decimal(o.accumulated_filled_qty)?,
+                trade_time: o.trade_time,
+            })?;
+        }
+        let mut client = self.db.get_handle().await?;
+        Ok(client.insert(table, block).await?)
+    }
+
+    async fn delete_force_orders(&self, table: &str, start: i64, end: i64) -> Result<()> {
+        let sql = format!(
+            "ALTER TABLE {} DELETE WHERE trade_time >= {} AND trade_time < {} SETTINGS mutations_sync = 1",
+            table, start, end
+        );
+        let mut client = self.db.get_handle().await?;
+        Ok(client.execute(sql).await?)
+    }
+}
+
+#[async_trait]
+impl crate::SnapshotRepo for ClickhouseQuery {
+    async fn insert_asset_snapshots(&self, rows: &[AssetSnapshot]) -> Result<()> {
+        if rows.is_empty() {
+            return Ok(());
+        }
+        let mut block = Block::new();
+        for r in rows {
+            block.push(row! {
+                time: r.time,
+                bot_id: r.bot_id,
+                asset: r.asset.clone(),
+                wallet_balance: to_ch_decimal(r.wallet_balance)?,
+                unrealized_profit: to_ch_decimal(r.unrealized_profit)?,
+                margin_balance: to_ch_decimal(r.margin_balance)?,
+                available_balance: to_ch_decimal(r.available_balance)?,
+                initial_margin: to_ch_decimal(r.initial_margin)?,
+                maint_margin: to_ch_decimal(r.maint_margin)?,
+            })?;
+        }
+        let mut client = self.db.get_handle().await?;
+        Ok(client.insert("bot_asset_snapshot", block).await?)
+    }
+
+    async fn insert_position_snapshots(&self, rows: &[PositionSnapshot]) -> Result<()> {
+        if rows.is_empty() {
+            return Ok(());
+        }
+        let mut block = Block::new();
+        for r in rows {
+            block.push(row! {
+                time: r.time,
+                bot_id: r.bot_id,
+                symbol: r.symbol.clone(),
+                side: r.side.clone(),
+                isolated: r.isolated as u8,
+                leverage: to_ch_decimal(r.leverage)?,
+                amount: to_ch_decimal(r.amount)?,
+                entry_price: to_ch_decimal(r.entry_price)?,
+                unrealized_profit: to_ch_decimal(r.unrealized_profit)?,
+                notional: to_ch_decimal(r.notional)?,
+                initial_margin: to_ch_decimal(r.initial_margin)?,
+                maint_margin: to_ch_decimal(r.maint_margin)?,
+            })?;
+        }
+        let mut client = self.db.get_handle().await?;
+        Ok(client.insert("bot_position_snapshot", block).await?)
+    }
+
+    async fn fetch_equity_curve(
+        &self,
+        bot_id: i64,
+        asset: &str,
+        start: &DateTime<Utc>,
+        to: &DateTime<Utc>,
+    ) -> Result<Vec<EquityPoint>> {
+        check_asset(asset)?;
+        let sql = format!(
+            "SELECT time, wallet_balance + unrealized_profit AS equity FROM bot_asset_snapshot FINAL \
+             WHERE bot_id = {} AND asset = '{}' AND time >= {} AND time < {} ORDER BY time",
+            bot_id,
+            asset,
+            start.timestamp_millis(),
+            to.timestamp_millis()
+        );
+        let mut client = self.db.get_handle().await?;
+        let mut stream = client.query(sql).stream();
+        let mut res = vec![];
+        while let Some(row) = stream.next().await {
+            let row = row?;
+            res.push(EquityPoint {
+                time: row.get("time")?,
+                equity: from_ch_decimal(row.get("equity")?),
+            });
+        }
+        Ok(res)
+    }
+}
+
+/// Asset codes are interpolated into SQL, so only `^[A-Z0-9]+$` is accepted.
+fn check_asset(asset: &str) -> Result<()> {
+    if asset.is_empty()
+        || !asset
+            .bytes()
+            .all(|b| b.is_ascii_uppercase() || b.is_ascii_digit())
+    {
+        return Err(Status::new(
+            400,
+            Reason::InvalidAsset,
+            format!("invalid asset {:?}", asset),
+        ));
+    }
+    Ok(())
+}
+
+/// Exact conversion to the `Decimal(18, 8)` columns used by every table.
+pub(crate) fn to_ch_decimal(v: Decimal) -> Result<clickhouse_rs::types::Decimal> {
+    to_ch_decimal_ps(v, CH_DECIMAL_PRECISION, CH_DECIMAL_SCALE)
+}
+
+/// Exact conversion to a `Decimal(precision, scale)` column. Values with more
+/// than `scale` fractional digits or more than `precision` digits in total are
+/// rejected instead of being rounded or wrapped.
+pub(crate) fn to_ch_decimal_ps(
+    v: Decimal,
+    precision: u32,
+    scale: u32,
+) -> Result<clickhouse_rs::types::Decimal> {
+    let overflow = |why: &str| {
+        Status::new(
+            400,
+            Reason::DecimalOverflow,
+            format!("{} {} Decimal({}, {})", v, why, precision, scale),
+        )
+    };
+    if precision > CH_DECIMAL_PRECISION || scale > precision {
+        return Err(overflow("cannot be stored as"));
+    }
+    if v.normalize().scale() > scale {
+        return Err(overflow("loses digits in"));
+    }
+    let mut exact = v;
+    exact.rescale(scale);
+    let underlying = i64::try_from(exact.mantissa())
+        .ok()
+        .filter(|m| m.unsigned_abs() < 10u64.pow(precision))
+        .ok_or_else(|| overflow("does not fit"))?;
+    Ok(clickhouse_rs::types::Decimal::new(underlying, scale as u8))
+}
+
+/// Exact conversion from any ClickHouse `Decimal(P, S)` value.
+pub(crate) fn from_ch_decimal(v: clickhouse_rs::types::Decimal) -> Decimal {
+    Decimal::new(v.internal::<i64>(), v.scale() as u32)
 }
 
 #[cfg(test)]
@@ -271,4 +556,58 @@ mod tests {
         let pre = ts - TimeDelta::minutes(10).num_milliseconds();
         println!("pre: {}", pre)
     }
+
+    #[test]
+    fn test_kline_table() {
+        let symbol = cex_market_symbol::Model {
+            id: 1,
+            exchange: "binance".to_string(),
+            market: "futures".to_string(),
+            symbol: "ETHUSDT".to_string(),
+            interval: "1m".to_string(),
+            base: "ETH".to_string(),
+            quote: "USDT".to_string(),
+            status: 1,
+            created_at: Default::default(),
+            updated_at: Default::default(),
+            deleted_at: None,
+        };
+        assert_eq!(kline_table(&symbol), "kline_ethusdt_1m");
+    }
+
+    #[test]
+    fn test_kline_range_sql() {
+        let start = DateTime::from_timestamp_millis(60_000).unwrap();
+        let to = DateTime::from_timestamp_millis(180_000).unwrap();
+        assert_eq!(
+            kline_range_sql("kline_ethusdt_1m", &start, &to),
+            "SELECT * FROM kline_ethusdt_1m FINAL WHERE id >= 60000 AND id < 180000 ORDER BY id"
+        );
+    }
+
+    #[test]
+    fn test_check_asset() {
+        assert!(check_asset("USDT").is_ok());
+        assert!(check_asset("1INCH").is_ok());
+        for asset in ["", "usdt", "USDT' OR '1'='1", "BTC-USD"] {
+            assert_eq!(check_asset(asset).unwrap_err().reason, Reason::InvalidAsset);
+        }
+    }
+
+    #[test]
+    fn test_ch_decimal() {
+        let v = Decimal::from_str("2001.12345678").unwrap();
+        let ch = to_ch_decimal(v).unwrap();
+        assert_eq!(ch.to_string(), "2001.12345678");
+        assert_eq!(from_ch_decimal(ch), v);
+        let ch = to_ch_decimal(Decimal::from_str("-0.5").unwrap()).unwrap();
+        assert_eq!(from_ch_decimal(ch).to_string(), "-0.50000000");
+        assert!(to_ch_decimal(Decimal::from_str("2001.123456789").unwrap()).is_err());
+        assert!(to_ch_decimal(Decimal::from_str("10000000000").unwrap()).is_err());
+
+        let ch = to_ch_decimal_ps(Decimal::from_str("12.5").unwrap(), 9, 2).unwrap();
+        assert_eq!(from_ch_decimal(ch), Decimal::from_str("12.50").unwrap());
+        assert!(to_ch_decimal_ps(Decimal::from_str("12345678").unwrap(), 9, 2).is_err());
+        assert!(to_ch_decimal_ps(Decimal::ONE, 38, 8).is_err());
+    }
 }
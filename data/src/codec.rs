@@ -0,0 +1,373 @@
+//! Versioned wire formats of the `domain` events: JSON for Redis streams and
+//! REST, and conversions to the `api` crate's protobuf types for gRPC.
+//! Decimals travel as strings in JSON so no digit is lost on the way.
+
+use std::{collections::HashMap, str::FromStr};
+
+use api::event::{EventAggTrade, EventBookTicker, EventForceOrder, EventKline, EventLiquidation};
+use ecode::{Reason, Result, Status};
+use redis::{from_redis_value, Value};
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+use serde::{Deserialize, Serialize};
+
+use crate::domain::{AggTrade, BookTicker, ForceOrderEvent, ForceOrderTally, KlineEvent};
+
+/// Current version of both wire formats. Bump it when a field changes meaning
+/// or is removed; adding a field does not need a bump.
+pub const EVENT_VERSION: u32 = 1;
+
+/// Any domain event, tagged with its kind.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum DomainEvent {
+    Kline(KlineEvent),
+    AggTrade(AggTrade),
+    ForceOrder(ForceOrderEvent),
+    BookTicker(BookTicker),
+    ForceOrderTally(ForceOrderTally),
+}
+
+/// JSON form: `{"v":1,"type":"kline","data":{...}}`.
+#[derive(Serialize, Deserialize)]
+struct Envelope<T> {
+    v: u32,
+    #[serde(flatten)]
+    event: T,
+}
+
+fn invalid_event(message: impl Into<String>) -> Status {
+    Status::new(400, Reason::InvalidEvent, message)
+}
+
+fn check_version(v: u32) -> Result<()> {
+    if v == 0 || v > EVENT_VERSION {
+        return Err(Status::new(
+            400,
+            Reason::UnsupportedEventVersion,
+            format!("event version {} is not in 1..={}", v, EVENT_VERSION),
+        ));
+    }
+    Ok(())
+}
+
+impl DomainEvent {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            DomainEvent::Kline(_) => "kline",
+            DomainEvent::AggTrade(_) => "agg_trade",
+            DomainEvent::ForceOrder(_) => "force_order",
+            DomainEvent::BookTicker(_) => "book_ticker",
+            DomainEvent::ForceOrderTally(_) => "force_order_tally",
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string(&Envelope {
+            v: EVENT_VERSION,
+            event: self,
+        })
+        .map_err(|e| invalid_event(e.to_string()))
+    }
+
+    pub fn from_json(s: &str) -> Result<Self> {
+        #[derive(Deserialize)]
+        struct Version {
+            v: u32,
+        }
+        let Version { v } = serde_json::from_str(s).map_err(|e| invalid_event(e.to_string()))?;
+        check_version(v)?;
+        let env: Envelope<DomainEvent> =
+            serde_json::from_str(s).map_err(|e| invalid_event(e.to_string()))?;
+        Ok(env.event)
+    }
+
+    /// Redis stream entry fields: the version, the kind and the JSON body.
+    pub fn to_stream_fields(&self) -> Result<[(&'static str, String); 3]> {
+        let data = match self {
+            DomainEvent::Kline(e) => serde_json::to_string(e),
+            DomainEvent::AggTrade(e) => serde_json::to_string(e),
+            DomainEvent::ForceOrder(e) => serde_json::to_string(e),
+            DomainEvent::BookTicker(e) => serde_json::to_string(e),
+            DomainEvent::ForceOrderTally(e) => serde_json::to_string(e),
+        }
+        .map_err(|e| invalid_event(e.to_string()))?;
+        Ok([
+            ("v", EVENT_VERSION.to_string()),
+            ("type", self.kind().to_string()),
+            ("data", data),
+        ])
+    }
+
+    /// Inverse of `to_stream_fields`, for entries read back with `XREAD`.
+    pub fn from_stream_fields(map: &HashMap<String, Value>) -> Result<Self> {
+        let field = |name: &str| -> Result<String> {
+            let v = map
+                .get(name)
+                .ok_or_else(|| invalid_event(format!("stream entry has no {} field", name)))?;
+            Ok(from_redis_value(v)?)
+        };
+        let v: u32 = field("v")?
+            .parse()
+            .map_err(|_| invalid_event("stream entry version is not a number"))?;
+        check_version(v)?;
+        let data = field("data")?;
+        let parse = |e: serde_json::Error| invalid_event(e.to_string());
+        match field("type")?.as_str() {
+            "kline" => Ok(DomainEvent::Kline(
+                serde_json::from_str(&data).map_err(parse)?,
+            )),
+            "agg_trade" => Ok(DomainEvent::AggTrade(
+                serde_json::from_str(&data).map_err(parse)?,
+            )),
+            "force_order" => Ok(DomainEvent::ForceOrder(
+                serde_json::from_str(&data).map_err(parse)?,
+            )),
+            "book_ticker" => Ok(DomainEvent::BookTicker(
+                serde_json::from_str(&data).map_err(parse)?,
+            )),
+            "force_order_tally" => Ok(DomainEvent::ForceOrderTally(
+                serde_json::from_str(&data).map_err(parse)?,
+            )),
+            ty => Err(invalid_event(format!("unknown event type {}", ty))),
+        }
+    }
+}
+
+fn wire_decimal(message: &str, field: &str, v: &str) -> Result<Decimal> {
+    Decimal::from_str(v).map_err(|e| {
+        invalid_event(format!(
+            "{}.{}: {:?} is not a decimal: {}",
+            message, field, v, e
+        ))
+    })
+}
+
+/// Copies the fields shared by a domain event and its `api` message. Decimals
+/// travel as strings, like in JSON.
+macro_rules! api_conversions {
+    ($domain:ident <=> $message:ident {
+        copy: [$($copy:ident),* $(,)?],
+        decimal: [$($dec:ident),* $(,)?] $(,)?
+    }) => {
+        impl From<&$domain> for $message {
+            fn from(e: &$domain) -> Self {
+                $message {
+                    $($copy: e.$copy.clone(),)*
+                    $($dec: e.$dec.to_string(),)*
+                }
+            }
+        }
+
+        impl TryFrom<&$message> for $domain {
+            type Error = Status;
+
+            fn try_from(m: &$message) -> Result<Self> {
+                Ok($domain {
+                    $($copy: m.$copy.clone(),)*
+                    $($dec: wire_decimal(stringify!($message), stringify!($dec), &m.$dec)?,)*
+                })
+            }
+        }
+    };
+}
+
+api_conversions!(KlineEvent <=> EventKline {
+    copy: [
+        exchange, market, id, event, symbol, start_time, end_time, interval, first_trade_id,
+        last_trade_id, trade_num,
+    ],
+    decimal: [
+        open, close, high, low, volume, quote_volume, active_buy_volume,
+        active_buy_quote_volume, ema7, ema25, macd, rsi,
+    ],
+});
+
+api_conversions!(AggTrade <=> EventAggTrade {
+    copy: [time, event, symbol, agg_trade_id, first_trade_id, last_trade_id, trade_time, maker],
+    decimal: [price, quantity],
+});
+
+api_conversions!(ForceOrderEvent <=> EventLiquidation {
+    copy: [
+        exchange, market, id, event, symbol, side, order_type, time_in_force, order_status,
+        trade_time,
+    ],
+    decimal: [orig_quantity, price, avg_price, last_filled_qty, accumulated_filled_qty],
+});
+
+api_conversions!(BookTicker <=> EventBookTicker {
+    copy: [exchange, market, symbol, update_id, event_time],
+    decimal: [bid_price, bid_qty, ask_price, ask_qty],
+});
+
+/// `EventForceOrder` carries quantities as doubles. They are read through
+/// their shortest decimal text, so `0.1` stays `0.1` instead of the nearest
+/// binary fraction.
+impl TryFrom<&EventForceOrder> for ForceOrderTally {
+    type Error = Status;
+
+    fn try_from(o: &EventForceOrder) -> Result<Self> {
+        let qty = |field: &str, v: f64| wire_decimal("EventForceOrder", field, &v.to_string());
+        Ok(ForceOrderTally {
+            exchange: o.exchange.clone(),
+            market: o.market.clone(),
+            symbol: o.symbol.clone(),
+            base: o.base.clone(),
+            quote: o.quote.clone(),
+            total_buy_quantity: qty("total_buy_quantity", o.total_buy_quantity)?,
+            total_sell_quantity: qty("total_sell_quantity", o.total_sell_quantity)?,
+        })
+    }
+}
+
+impl TryFrom<&ForceOrderTally> for EventForceOrder {
+    type Error = Status;
+
+    fn try_from(t: &ForceOrderTally) -> Result<Self> {
+        let qty = |field: &str, v: Decimal| {
+            v.to_f64().ok_or_else(|| {
+                invalid_event(format!(
+                    "ForceOrderTally.{}: {} does not fit a double",
+                    field, v
+                ))
+            })
+        };
+        Ok(EventForceOrder {
+            exchange: t.exchange.clone(),
+            market: t.market.clone(),
+            symbol: t.symbol.clone(),
+            base: t.base.clone(),
+            quote: t.quote.clone(),
+            total_buy_quantity: qty("total_buy_quantity", t.total_buy_quantity)?,
+            total_sell_quantity: qty("total_sell_quantity", t.total_sell_quantity)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn kline() -> KlineEvent {
+        KlineEvent {
+            exchange: "binance".to_string(),
+            market: "futures".to_string(),
+            id: 1700000000000,
+            event: "kline".to_string(),
+            symbol: "ETHUSDT".to_string(),
+            start_time: 1700000000000,
+            end_time: 1700000059999,
+            interval: "1m".to_string(),
+            first_trade_id: 1,
+            last_trade_id: 9,
+            open: dec!(2000.10),
+            close: dec!(2001.5),
+            high: dec!(2002),
+            low: dec!(1999.99999999),
+            volume: dec!(12.345),
+            trade_num: 9,
+            ema7: dec!(2000.123456789012345678),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let ev = DomainEvent::Kline(kline());
+        let json = ev.to_json().unwrap();
+        assert!(json.starts_with(r#"{"v":1,"type":"kline","data":{"#));
+        assert!(json.contains(r#""open":"2000.10""#));
+        assert_eq!(DomainEvent::from_json(&json).unwrap(), ev);
+
+        let err = DomainEvent::from_json(&json.replacen(r#""v":1"#, r#""v":2"#, 1)).unwrap_err();
+        assert_eq!(err.reason, Reason::UnsupportedEventVersion);
+    }
+
+    #[test]
+    fn test_stream_fields_round_trip() {
+        let ev = DomainEvent::ForceOrder(ForceOrderEvent {
+            id: 3,
+            symbol: "ETHUSDT".to_string(),
+            price: dec!(1999.5),
+            ..Default::default()
+        });
+        let map: HashMap<String, Value> = ev
+            .to_stream_fields()
+            .unwrap()
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), Value::Data(v.into_bytes())))
+            .collect();
+        assert_eq!(DomainEvent::from_stream_fields(&map).unwrap(), ev);
+    }
+
+    #[test]
+    fn test_api_force_order_round_trip() {
+        let tally = ForceOrderTally {
+            exchange: "binance".to_string(),
+            market: "futures".to_string(),
+            symbol: "ETHUSDT".to_string(),
+            base: "ETH".to_string(),
+            quote: "USDT".to_string(),
+            total_buy_quantity: dec!(12.5),
+            total_sell_quantity: dec!(0.001),
+        };
+        let ev = EventForceOrder::try_from(&tally).unwrap();
+        assert_eq!(ev.symbol, "ETHUSDT");
+        assert_eq!(ForceOrderTally::try_from(&ev).unwrap(), tally);
+
+        let nan = EventForceOrder {
+            total_buy_quantity: f64::NAN,
+            ..ev
+        };
+        let err = ForceOrderTally::try_from(&nan).unwrap_err();
+        assert_eq!(err.reason, Reason::InvalidEvent);
+
+        let ev = DomainEvent::ForceOrderTally(tally);
+        assert_eq!(DomainEvent::from_json(&ev.to_json().unwrap()).unwrap(), ev);
+    }
+
+    #[test]
+    fn test_api_event_round_trip() {
+        let k = kline();
+        let m = EventKline::from(&k);
+        assert_eq!(m.open, "2000.10");
+        assert_eq!(m.ema7, "2000.123456789012345678");
+        assert_eq!(KlineEvent::try_from(&m).unwrap(), k);
+
+        let t = AggTrade {
+            time: 1,
+            symbol: "ETHUSDT".to_string(),
+            price: dec!(2000.01),
+            quantity: dec!(0.5),
+            maker: true,
+            ..Default::default()
+        };
+        assert_eq!(AggTrade::try_from(&EventAggTrade::from(&t)).unwrap(), t);
+
+        let f = ForceOrderEvent {
+            id: 3,
+            side: "SELL".to_string(),
+            avg_price: dec!(1999.5),
+            ..Default::default()
+        };
+        assert_eq!(
+            ForceOrderEvent::try_from(&EventLiquidation::from(&f)).unwrap(),
+            f
+        );
+
+        let b = BookTicker {
+            symbol: "ETHUSDT".to_string(),
+            bid_price: dec!(2000),
+            ask_price: dec!(2000.01),
+            ..Default::default()
+        };
+        let mut m = EventBookTicker::from(&b);
+        assert_eq!(BookTicker::try_from(&m).unwrap(), b);
+
+        m.ask_qty = "1,5".to_string();
+        let err = BookTicker::try_from(&m).unwrap_err();
+        assert_eq!(err.reason, Reason::InvalidEvent);
+        assert!(err.message.contains("EventBookTicker.ask_qty"));
+    }
+}
@@ -1,11 +1,17 @@
-use clickhouse_rs::types::Decimal;
+use chrono::TimeDelta;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
 use validator::{Validate, ValidationError};
 
+/// Prices and quantities are never negative.
 fn validate_decimal(v: &Decimal) -> Result<(), ValidationError> {
+    if v.is_sign_negative() && !v.is_zero() {
+        return Err(ValidationError::new("negative"));
+    }
     Ok(())
 }
 
-#[derive(Clone, Debug, PartialEq, Default, Validate)]
+#[derive(Clone, Debug, PartialEq, Default, Validate, Serialize, Deserialize)]
 pub struct AggTrade {
     #[validate(range(min = 1))]
     pub time: i64,
@@ -15,7 +21,9 @@ pub struct AggTrade {
     pub symbol: String,
     #[validate(range(min = 1))]
     pub agg_trade_id: i64,
+    #[validate(custom = "validate_decimal")]
     pub price: Decimal,
+    #[validate(custom = "validate_decimal")]
     pub quantity: Decimal,
     pub first_trade_id: i64,
     pub last_trade_id: i64,
@@ -23,7 +31,7 @@ pub struct AggTrade {
     pub maker: bool,
 }
 
-#[derive(Clone, Debug, PartialEq, Default, Validate)]
+#[derive(Clone, Debug, PartialEq, Default, Validate, Serialize, Deserialize)]
 pub struct KlineEvent {
     #[validate(length(min = 1))]
     pub exchange: String,
@@ -45,15 +53,23 @@ pub struct KlineEvent {
     pub first_trade_id: i64,
     #[validate(range(min = 1))]
     pub last_trade_id: i64,
+    #[validate(custom = "validate_decimal")]
     pub open: Decimal,
+    #[validate(custom = "validate_decimal")]
     pub close: Decimal,
+    #[validate(custom = "validate_decimal")]
     pub high: Decimal,
+    #[validate(custom = "validate_decimal")]
     pub low: Decimal,
+    #[validate(custom = "validate_decimal")]
     pub volume: Decimal,
     #[validate(range(min = 0))]
     pub trade_num: i64,
+    #[validate(custom = "validate_decimal")]
     pub quote_volume: Decimal,
+    #[validate(custom = "validate_decimal")]
     pub active_buy_volume: Decimal,
+    #[validate(custom = "validate_decimal")]
     pub active_buy_quote_volume: Decimal,
     pub ema7: Decimal,
     pub ema25: Decimal,
@@ -61,7 +77,7 @@ pub struct KlineEvent {
     pub rsi: Decimal,
 }
 
-#[derive(Clone, Debug, PartialEq, Default, Validate)]
+#[derive(Clone, Debug, PartialEq, Default, Validate, Serialize, Deserialize)]
 pub struct ForceOrderEvent {
     #[validate(length(min = 1))]
     pub exchange: String,
@@ -79,12 +95,95 @@ pub struct ForceOrderEvent {
     pub order_type: String,
     #[validate(length(min = 1))]
     pub time_in_force: String,
+    #[validate(custom = "validate_decimal")]
     pub orig_quantity: Decimal,
+    #[validate(custom = "validate_decimal")]
     pub price: Decimal,
+    #[validate(custom = "validate_decimal")]
     pub avg_price: Decimal,
     pub order_status: String,
+    #[validate(custom = "validate_decimal")]
     pub last_filled_qty: Decimal,
+    #[validate(custom = "validate_decimal")]
     pub accumulated_filled_qty: Decimal,
     #[validate(range(min = 0))]
     pub trade_time: i64,
 }
+
+/// Best bid and ask of a symbol.
+#[derive(Clone, Debug, PartialEq, Default, Validate, Serialize, Deserialize)]
+pub struct BookTicker {
+    #[validate(length(min = 1))]
+    pub exchange: String,
+    #[validate(length(min = 1))]
+    pub market: String,
+    #[validate(length(min = 1))]
+    pub symbol: String,
+    pub update_id: i64,
+    #[validate(custom = "validate_decimal")]
+    pub bid_price: Decimal,
+    #[validate(custom = "validate_decimal")]
+    pub bid_qty: Decimal,
+    #[validate(custom = "validate_decimal")]
+    pub ask_price: Decimal,
+    #[validate(custom = "validate_decimal")]
+    pub ask_qty: Decimal,
+    pub event_time: i64,
+}
+
+/// Liquidated quantity per side of a symbol, as fed to the TA stream.
+#[derive(Clone, Debug, PartialEq, Default, Validate, Serialize, Deserialize)]
+pub struct ForceOrderTally {
+    #[validate(length(min = 1))]
+    pub exchange: String,
+    #[validate(length(min = 1))]
+    pub market: String,
+    #[validate(length(min = 1))]
+    pub symbol: String,
+    pub base: String,
+    pub quote: String,
+    #[validate(custom = "validate_decimal")]
+    pub total_buy_quantity: Decimal,
+    #[validate(custom = "validate_decimal")]
+    pub total_sell_quantity: Decimal,
+}
+
+/// One sampled close of a kline series, keyed by the kline open time (ms).
+#[derive(Clone, Debug, PartialEq, Default, Serialize, Deserialize)]
+pub struct ClosePrice {
+    pub id: i64,
+    pub close: Decimal,
+}
+
+/// Parses a Binance style interval (`1m`, `15m`, `4h`, `1d`, `1w`) into its duration.
+pub fn interval_duration(interval: &str) -> Option<TimeDelta> {
+    // intervals come from events, file names and table names, so anything
+    // that is not ASCII is rejected before slicing by bytes
+    if interval.len() < 2 || !interval.is_ascii() {
+        return None;
+    }
+    let (n, unit) = interval.split_at(interval.len() - 1);
+    let n: i64 = n.parse().ok().filter(|n| *n > 0)?;
+    match unit {
+        "s" => TimeDelta::try_seconds(n),
+        "m" => TimeDelta::try_minutes(n),
+        "h" => TimeDelta::try_hours(n),
+        "d" => TimeDelta::try_days(n),
+        "w" => TimeDelta::try_weeks(n),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interval_duration() {
+        assert_eq!(interval_duration("15m"), Some(TimeDelta::minutes(15)));
+        assert_eq!(interval_duration("1w"), Some(TimeDelta::weeks(1)));
+        for bad in ["", "m", "0m", "-1m", "1x", "1é", "é1m", "9223372036854775807w"] {
+            assert_eq!(interval_duration(bad), None, "{}", bad);
+        }
+    }
+}
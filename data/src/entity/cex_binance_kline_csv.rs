@@ -12,6 +12,8 @@ pub struct Model {
     pub interval: String,
     pub start_time: Option<i64>,
     pub end_time: Option<i64>,
+    pub exchange: String,
+    pub market: String,
     pub created_at: Option<DateTime>,
     pub updated_at: Option<DateTime>,
     pub deleted_at: Option<DateTime>,
@@ -11,7 +11,7 @@ pub struct Model {
     pub ty: String,
     pub side: String,
     pub symbol: String,
-    pub order_id: u64,
+    pub order_id: i64,
     pub time_in_force: String,
     pub original_qty: String,
     pub original_price: String,
@@ -11,7 +11,7 @@ pub struct Model {
     pub ty: i32,
     pub symbol: String,
     pub client_order_id: String,
-    pub order_id: u64,
+    pub order_id: i64,
     pub status: i32,
     pub price: String,
     pub quantity: String,
@@ -19,7 +19,7 @@ pub struct Model {
     pub real_quantity: Option<String>,
     pub real_quote_qty: Option<String>,
     pub quote_fee: Option<String>,
-    pub cancel_order_id: Option<u64>,
+    pub cancel_order_id: Option<i64>,
     pub cancel_order_status: Option<i32>,
     pub buy_order_ids: Json,
     pub profit: Option<String>,
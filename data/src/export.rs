@@ -0,0 +1,164 @@
+//! Kline exports for research: any `KlineRepo` can hand out a time range as
+//! a polars `DataFrame` or write it to a Parquet or CSV file.
+
+use std::{fs::File, path::Path};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use ecode::{Reason, Result, Status};
+use polars::prelude::{CsvWriter, DataFrame, NamedFrom, ParquetWriter, SerWriter, Series};
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+use serde::{Deserialize, Serialize};
+
+use crate::{domain::KlineEvent, KlineRepo};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    Parquet,
+    Csv,
+}
+
+impl ExportFormat {
+    /// Picks the format from a `.parquet` or `.csv` extension.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "parquet" => Some(ExportFormat::Parquet),
+            "csv" => Some(ExportFormat::Csv),
+            _ => None,
+        }
+    }
+}
+
+/// One row per kline in the given order, keyed by `id` (open time in ms).
+/// Prices, volumes and indicators become `f64` columns, which is what
+/// notebooks work with; the exact values stay in ClickHouse.
+pub fn klines_to_frame(klines: &[KlineEvent]) -> Result<DataFrame> {
+    let int = |name: &str, f: fn(&KlineEvent) -> i64| {
+        Series::new(name, klines.iter().map(f).collect::<Vec<_>>())
+    };
+    let text = |name: &str, f: fn(&KlineEvent) -> &str| {
+        Series::new(name, klines.iter().map(f).collect::<Vec<_>>())
+    };
+    let float = |name: &str, f: fn(&KlineEvent) -> Decimal| {
+        Series::new(
+            name,
+            klines
+                .iter()
+                .map(|k| f(k).to_f64().unwrap_or(f64::NAN))
+                .collect::<Vec<_>>(),
+        )
+    };
+    Ok(DataFrame::new(vec![
+        int("id", |k| k.id),
+        text("symbol", |k| &k.symbol),
+        text("interval", |k| &k.interval),
+        int("start_time", |k| k.start_time),
+        int("end_time", |k| k.end_time),
+        float("open", |k| k.open),
+        float("high", |k| k.high),
+        float("low", |k| k.low),
+        float("close", |k| k.close),
+        float("volume", |k| k.volume),
+        float("quote_volume", |k| k.quote_volume),
+        int("trade_num", |k| k.trade_num),
+        float("active_buy_volume", |k| k.active_buy_volume),
+        float("active_buy_quote_volume", |k| k.active_buy_quote_volume),
+        float("ema7", |k| k.ema7),
+        float("ema25", |k| k.ema25),
+        float("macd", |k| k.macd),
+        float("rsi", |k| k.rsi),
+    ])?)
+}
+
+/// Writes `df` to `path`, replacing any existing file.
+pub async fn write_frame(mut df: DataFrame, path: &Path, format: ExportFormat) -> Result<()> {
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        let file = File::create(&path)?;
+        match format {
+            ExportFormat::Parquet => {
+                ParquetWriter::new(file).finish(&mut df)?;
+            }
+            ExportFormat::Csv => CsvWriter::new(file).finish(&mut df)?,
+        }
+        Ok::<_, Status>(())
+    })
+    .await
+    .map_err(|e| Status::new(500, Reason::IoError, e.to_string()))?
+}
+
+/// Export helpers available on every `KlineRepo`.
+#[async_trait]
+pub trait KlineExport: KlineRepo + Sync {
+    /// The klines opening in `[start, to)`, oldest first.
+    async fn kline_frame(
+        &self,
+        table: &str,
+        start: &DateTime<Utc>,
+        to: &DateTime<Utc>,
+    ) -> Result<DataFrame> {
+        let klines = self.fetch_kline_range(table, start, to).await?;
+        klines_to_frame(&klines)
+    }
+
+    /// Writes `kline_frame` to `path` in the format of its extension and
+    /// returns the number of rows.
+    async fn export_klines(
+        &self,
+        table: &str,
+        start: &DateTime<Utc>,
+        to: &DateTime<Utc>,
+        path: &Path,
+    ) -> Result<usize> {
+        let format = ExportFormat::from_path(path).ok_or_else(|| {
+            Status::new(
+                400,
+                Reason::UnsupportedExportFormat,
+                format!("{}: expected a .parquet or .csv file", path.display()),
+            )
+        })?;
+        let df = self.kline_frame(table, start, to).await?;
+        let rows = df.height();
+        write_frame(df, path, format).await?;
+        Ok(rows)
+    }
+}
+
+impl<K: KlineRepo + Sync> KlineExport for K {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_klines_to_frame() {
+        let klines: Vec<KlineEvent> = (1..=3)
+            .map(|i| KlineEvent {
+                id: i * 60_000,
+                symbol: "ETHUSDT".to_string(),
+                close: dec!(2281.5) + Decimal::from(i),
+                ..Default::default()
+            })
+            .collect();
+        let df = klines_to_frame(&klines).unwrap();
+        assert_eq!(df.height(), 3);
+        assert_eq!(df.get_column_names()[..3], ["id", "symbol", "interval"]);
+        assert!(df.column("close").is_ok());
+        assert_eq!(klines_to_frame(&[]).unwrap().height(), 0);
+    }
+
+    #[test]
+    fn test_export_format() {
+        assert_eq!(
+            ExportFormat::from_path(Path::new("/tmp/eth.parquet")),
+            Some(ExportFormat::Parquet)
+        );
+        assert_eq!(
+            ExportFormat::from_path(Path::new("eth.csv")),
+            Some(ExportFormat::Csv)
+        );
+        assert_eq!(ExportFormat::from_path(Path::new("eth.json")), None);
+    }
+}
@@ -1,20 +1,268 @@
-use std::sync::Arc;
+//! Snowflake ids: 41 bits of milliseconds since the Unix epoch, a 10 bit
+//! worker id and a 12 bit sequence. The layout matches the ids issued so far
+//! with machine and node ids of 5 bits each, so old and new ids still sort
+//! together and decode the same way.
 
-use tokio::sync::Mutex;
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
+use chrono::{DateTime, Utc};
+use ecode::{Reason, Result, Status};
+use serde::{Deserialize, Serialize};
+use tokio_graceful::ShutdownGuard;
+
+use crate::cache::RedisQuery;
+
+pub const WORKER_ID_BITS: u32 = 10;
+pub const SEQUENCE_BITS: u32 = 12;
+pub const MAX_WORKER_ID: u16 = (1 << WORKER_ID_BITS) - 1;
+const SEQUENCE_MASK: u64 = (1 << SEQUENCE_BITS) - 1;
+const TIMESTAMP_SHIFT: u32 = WORKER_ID_BITS + SEQUENCE_BITS;
+
+fn default_lease_ttl_ms() -> u64 {
+    30_000
+}
+
+fn default_max_rollback_ms() -> u64 {
+    10
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IdConf {
+    /// Fixed worker id. When unset a free one is leased from Redis.
+    #[serde(default)]
+    pub worker_id: Option<u16>,
+    #[serde(default = "default_lease_ttl_ms")]
+    pub lease_ttl_ms: u64,
+    /// Clock steps back up to this far are absorbed by reusing the last
+    /// timestamp; larger ones fail `IDGen::id`.
+    #[serde(default = "default_max_rollback_ms")]
+    pub max_rollback_ms: u64,
+}
+
+impl Default for IdConf {
+    fn default() -> Self {
+        IdConf {
+            worker_id: None,
+            lease_ttl_ms: default_lease_ttl_ms(),
+            max_rollback_ms: default_max_rollback_ms(),
+        }
+    }
+}
+
+/// The fields of an id, see `decode`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IdParts {
+    pub timestamp_ms: i64,
+    pub worker_id: u16,
+    pub sequence: u16,
+}
+
+impl IdParts {
+    pub fn time(&self) -> Option<DateTime<Utc>> {
+        DateTime::from_timestamp_millis(self.timestamp_ms)
+    }
+}
+
+pub fn decode(id: i64) -> IdParts {
+    let id = id as u64;
+    IdParts {
+        timestamp_ms: (id >> TIMESTAMP_SHIFT) as i64,
+        worker_id: ((id >> SEQUENCE_BITS) & MAX_WORKER_ID as u64) as u16,
+        sequence: (id & SEQUENCE_MASK) as u16,
+    }
+}
+
+fn worker_lock(worker_id: u16) -> String {
+    format!("id:worker:{}", worker_id)
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+/// Lock-free id generator; share it behind an `Arc`.
 #[derive(Debug)]
 pub struct IDGen {
-    sf: snowflake::SnowflakeIdGenerator,
+    worker_id: u16,
+    max_rollback_ms: u64,
+    /// Last issued `timestamp << SEQUENCE_BITS | sequence`.
+    state: AtomicU64,
+    /// Cleared when a leased worker id is lost.
+    valid: Arc<AtomicBool>,
 }
 
 impl IDGen {
-    pub async fn new() -> Arc<Mutex<IDGen>> {
-        Arc::new(Mutex::new(IDGen {
-            sf: snowflake::SnowflakeIdGenerator::new(1, 1),
-        }))
+    pub fn new(worker_id: u16, max_rollback_ms: u64) -> Result<Self> {
+        if worker_id > MAX_WORKER_ID {
+            return Err(Status::new(
+                400,
+                Reason::InvalidWorkerId,
+                format!("worker id {} is above {}", worker_id, MAX_WORKER_ID),
+            ));
+        }
+        Ok(IDGen {
+            worker_id,
+            max_rollback_ms,
+            state: AtomicU64::new(0),
+            valid: Arc::new(AtomicBool::new(true)),
+        })
+    }
+
+    /// Uses `conf.worker_id` if set and leases one from Redis otherwise.
+    pub async fn from_conf(conf: &IdConf, redis: &RedisQuery, guard: ShutdownGuard) -> Result<Self> {
+        match conf.worker_id {
+            Some(worker_id) => IDGen::new(worker_id, conf.max_rollback_ms),
+            None => {
+                let ttl = Duration::from_millis(conf.lease_ttl_ms);
+                IDGen::leased(redis, ttl, conf.max_rollback_ms, guard).await
+            }
+        }
+    }
+
+    /// Leases the first free worker id, starting from one derived from the
+    /// process id so replicas rarely contend for the same one. The lease is
+    /// renewed until shutdown; if it is lost anyway the generator stops
+    /// issuing ids, since another replica may take the worker id over.
+    pub async fn leased(
+        redis: &RedisQuery,
+        ttl: Duration,
+        max_rollback_ms: u64,
+        guard: ShutdownGuard,
+    ) -> Result<Self> {
+        let start = std::process::id() as u16 & MAX_WORKER_ID;
+        for i in 0..=MAX_WORKER_ID {
+            let worker_id = (start + i) & MAX_WORKER_ID;
+            let Some(lease) = redis.try_acquire_lease(&worker_lock(worker_id), ttl).await? else {
+                continue;
+            };
+            let generator = IDGen::new(worker_id, max_rollback_ms)?;
+            let valid = generator.valid.clone();
+            let mut held = redis.keep_lease(lease);
+            tokio::spawn(async move {
+                tokio::select! {
+                    _ = guard.cancelled() => {
+                        valid.store(false, Ordering::Release);
+                        if let Err(e) = held.release().await {
+                            log::error!("id worker {}: release: {}", worker_id, e);
+                        }
+                    }
+                    _ = held.lost() => valid.store(false, Ordering::Release),
+                }
+            });
+            log::info!("id worker {} leased", worker_id);
+            return Ok(generator);
+        }
+        Err(Status::new(
+            503,
+            Reason::WorkerIdUnavailable,
+            "every worker id is leased",
+        ))
+    }
+
+    pub fn worker_id(&self) -> u16 {
+        self.worker_id
+    }
+
+    pub fn id(&self) -> Result<i64> {
+        if !self.valid.load(Ordering::Acquire) {
+            return Err(Status::new(
+                503,
+                Reason::WorkerIdUnavailable,
+                format!("worker id {} is no longer leased", self.worker_id),
+            ));
+        }
+        let mut state = self.state.load(Ordering::Relaxed);
+        loop {
+            let now = now_ms();
+            let last = state >> SEQUENCE_BITS;
+            let next = if now > last {
+                now << SEQUENCE_BITS
+            } else if last - now > self.max_rollback_ms {
+                return Err(Status::new(
+                    500,
+                    Reason::ClockMovedBackwards,
+                    format!("clock moved back {}ms", last - now),
+                ));
+            } else if state & SEQUENCE_MASK < SEQUENCE_MASK {
+                state + 1
+            } else {
+                // Sequence exhausted for this millisecond.
+                std::hint::spin_loop();
+                state = self.state.load(Ordering::Relaxed);
+                continue;
+            };
+            match self
+                .state
+                .compare_exchange_weak(state, next, Ordering::Relaxed, Ordering::Relaxed)
+            {
+                Ok(_) => {
+                    let ts = next >> SEQUENCE_BITS;
+                    let seq = next & SEQUENCE_MASK;
+                    let worker = (self.worker_id as u64) << SEQUENCE_BITS;
+                    return Ok((ts << TIMESTAMP_SHIFT | worker | seq) as i64);
+                }
+                Err(actual) => state = actual,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_decode() {
+        let generator = IDGen::new(37, 10).unwrap();
+        let before = now_ms() as i64;
+        let id = generator.id().unwrap();
+        let parts = decode(id);
+        assert_eq!(parts.worker_id, 37);
+        assert!(parts.timestamp_ms >= before && parts.timestamp_ms <= now_ms() as i64);
+        assert!(parts.time().is_some());
+        assert!(IDGen::new(MAX_WORKER_ID + 1, 10).is_err());
+    }
+
+    #[test]
+    fn test_unique_across_threads() {
+        let generator = Arc::new(IDGen::new(1, 10).unwrap());
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let generator = generator.clone();
+                std::thread::spawn(move || {
+                    (0..10_000).map(|_| generator.id().unwrap()).collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        let mut seen = HashSet::new();
+        for handle in handles {
+            let ids = handle.join().unwrap();
+            assert!(ids.windows(2).all(|w| w[0] < w[1]));
+            assert!(ids.into_iter().all(|id| seen.insert(id)));
+        }
     }
 
-    pub async fn id(&mut self) -> i64 {
-        self.sf.generate()
+    #[test]
+    fn test_clock_rollback() {
+        let generator = IDGen::new(1, 10).unwrap();
+        // Pretend the last id came from slightly in the future.
+        generator
+            .state
+            .store((now_ms() + 5) << SEQUENCE_BITS, Ordering::Relaxed);
+        assert!(generator.id().is_ok());
+        generator
+            .state
+            .store((now_ms() + 60_000) << SEQUENCE_BITS, Ordering::Relaxed);
+        let err = generator.id().unwrap_err();
+        assert_eq!(err.reason, Reason::ClockMovedBackwards);
     }
 }
@@ -0,0 +1,756 @@
+//! Backfills from Binance's public data archives (data.binance.vision):
+//! daily or monthly files named `{SYMBOL}-{kind}-{YYYY-MM[-DD]}`, where the
+//! kind is the kline interval, `aggTrades` or `liquidationSnapshot`. Files
+//! are CSV, zipped or not, or Parquet with the CSV column names.
+//!
+//! Every imported file is recorded through `ImportJobRepo`, so later runs
+//! skip it. A file interrupted halfway is imported again from the start.
+//! Aggregated trades keep their ids, and their `ReplacingMergeTree` tables
+//! collapse the duplicates on merge. Klines and liquidations have their
+//! period cleared first instead: liquidations live in a plain `MergeTree`,
+//! and klines feed resampled tables whose sums would count a row twice.
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    ops::AddAssign,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use async_trait::async_trait;
+use chrono::{Months, NaiveDate};
+use ecode::{Reason, Result, Status};
+use polars::prelude::{DataType, ParquetReader, SerReader};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use crate::{
+    clickhouse::{aggtrade_table_name, kline_table_name, liquidation_table_name},
+    domain::{interval_duration, AggTrade, ForceOrderEvent, KlineEvent},
+    ingest::EventValidator,
+    AggTradeRepo, ForceOrderRepo, ImportJobRepo, ImportPeriod, KlineRepo,
+};
+
+pub const EVENT_KLINE: &str = "kline";
+pub const EVENT_AGG_TRADE: &str = "aggTrade";
+pub const EVENT_FORCE_ORDER: &str = "forceOrder";
+
+fn default_exchange() -> String {
+    "binance".to_string()
+}
+
+fn default_market() -> String {
+    "futures".to_string()
+}
+
+fn default_batch_size() -> usize {
+    10_000
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CsvConf {
+    /// Root of the downloaded archives, searched recursively.
+    #[serde(default)]
+    pub path: String,
+    /// Exchange and market stamped on the imported events.
+    #[serde(default = "default_exchange")]
+    pub exchange: String,
+    #[serde(default = "default_market")]
+    pub market: String,
+    /// Rows per ClickHouse insert.
+    #[serde(default = "default_batch_size")]
+    pub batch_size: usize,
+}
+
+impl Default for CsvConf {
+    fn default() -> Self {
+        CsvConf {
+            path: String::new(),
+            exchange: default_exchange(),
+            market: default_market(),
+            batch_size: default_batch_size(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImportReport {
+    pub files: usize,
+    /// Files already covered by an earlier import.
+    pub skipped: usize,
+    pub failed: usize,
+    pub rows: usize,
+    /// Rows dropped by validation.
+    pub rejected: usize,
+}
+
+impl AddAssign for ImportReport {
+    fn add_assign(&mut self, rhs: Self) {
+        self.files += rhs.files;
+        self.skipped += rhs.skipped;
+        self.failed += rhs.failed;
+        self.rows += rhs.rows;
+        self.rejected += rhs.rejected;
+    }
+}
+
+/// An archive and the period its name says it holds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArchiveFile {
+    pub path: PathBuf,
+    pub period: ImportPeriod,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Csv,
+    Zip,
+    Parquet,
+}
+
+impl ArchiveFile {
+    /// Parses kline archive names such as `BTCUSDT-1m-2024-01.zip` (monthly)
+    /// or `BTCUSDT-1m-2024-01-15.csv` (daily). `None` for anything else.
+    pub fn kline(path: &Path) -> Option<Self> {
+        let (symbol, interval, date) = split_name(path)?;
+        interval_duration(interval)?;
+        Self::new(path, EVENT_KLINE, symbol, interval, date)
+    }
+
+    /// `BTCUSDT-aggTrades-2024-01-15.zip` and the like.
+    pub fn agg_trade(path: &Path) -> Option<Self> {
+        let (symbol, kind, date) = split_name(path)?;
+        (kind == "aggTrades").then_some(())?;
+        Self::new(path, EVENT_AGG_TRADE, symbol, "", date)
+    }
+
+    /// `BTCUSDT-liquidationSnapshot-2024-01-15.zip` and the like.
+    pub fn liquidation(path: &Path) -> Option<Self> {
+        let (symbol, kind, date) = split_name(path)?;
+        (kind == "liquidationSnapshot").then_some(())?;
+        Self::new(path, EVENT_FORCE_ORDER, symbol, "", date)
+    }
+
+    fn new(path: &Path, event: &str, symbol: &str, interval: &str, date: &str) -> Option<Self> {
+        let (start_time, end_time) = date_range(date)?;
+        Some(ArchiveFile {
+            path: path.to_path_buf(),
+            period: ImportPeriod {
+                event: event.to_string(),
+                symbol: symbol.to_string(),
+                interval: interval.to_string(),
+                start_time,
+                end_time,
+                ..Default::default()
+            },
+        })
+    }
+
+    fn format(&self) -> Format {
+        match self.path.extension().and_then(|e| e.to_str()) {
+            Some("zip") => Format::Zip,
+            Some("parquet") => Format::Parquet,
+            _ => Format::Csv,
+        }
+    }
+}
+
+/// `(symbol, kind, date)` of an archive name with a known extension.
+fn split_name(path: &Path) -> Option<(&str, &str, &str)> {
+    let name = path.file_name()?.to_str()?;
+    let stem = [".zip", ".csv", ".parquet"]
+        .iter()
+        .find_map(|ext| name.strip_suffix(ext))?;
+    let mut parts = stem.splitn(3, '-');
+    Some((parts.next()?, parts.next()?, parts.next()?))
+}
+
+/// `YYYY-MM` or `YYYY-MM-DD` as `[start, end)` in ms.
+fn date_range(date: &str) -> Option<(i64, i64)> {
+    let (start, end) = match date.len() {
+        7 => {
+            let start = NaiveDate::parse_from_str(&format!("{}-01", date), "%Y-%m-%d").ok()?;
+            (start, start.checked_add_months(Months::new(1))?)
+        }
+        10 => {
+            let start = NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()?;
+            (start, start.succ_opt()?)
+        }
+        _ => return None,
+    };
+    let ms = |d: NaiveDate| Some(d.and_hms_opt(0, 0, 0)?.and_utc().timestamp_millis());
+    Some((ms(start)?, ms(end)?))
+}
+
+/// Every file under `dir` that `parse` recognises, ordered by stream and
+/// then by period.
+pub async fn scan(dir: &Path, parse: fn(&Path) -> Option<ArchiveFile>) -> Result<Vec<ArchiveFile>> {
+    let dir = dir.to_path_buf();
+    let mut files = tokio::task::spawn_blocking(move || {
+        let mut files = vec![];
+        let mut dirs = vec![dir];
+        while let Some(dir) = dirs.pop() {
+            for entry in std::fs::read_dir(&dir)? {
+                let path = entry?.path();
+                if path.is_dir() {
+                    dirs.push(path);
+                } else if let Some(file) = parse(&path) {
+                    files.push(file);
+                }
+            }
+        }
+        Ok::<_, Status>(files)
+    })
+    .await
+    .map_err(|e| Status::new(500, Reason::IoError, e.to_string()))??;
+    files.sort_by(|a, b| {
+        let key = |f: &ArchiveFile| {
+            (
+                f.period.symbol.clone(),
+                f.period.interval.clone(),
+                f.period.start_time,
+            )
+        };
+        key(a).cmp(&key(b))
+    });
+    Ok(files)
+}
+
+fn archive_error(path: &Path, message: impl std::fmt::Display) -> Status {
+    Status::new(400, Reason::InvalidArchive, format!("{}: {}", path.display(), message))
+}
+
+type Record = Vec<String>;
+
+/// Streams the records of `file`, `batch` at a time, from a blocking thread.
+/// A zip archive is expected to hold one CSV file; Parquet columns are
+/// picked by name in `columns` order and read as text.
+fn read_records(
+    file: &ArchiveFile,
+    columns: &'static [&'static str],
+    batch: usize,
+) -> mpsc::Receiver<Result<Vec<Record>>> {
+    let (tx, rx) = mpsc::channel(2);
+    let path = file.path.clone();
+    let format = file.format();
+    tokio::task::spawn_blocking(move || {
+        let res = (|| {
+            let f = File::open(&path)?;
+            match format {
+                Format::Csv => send_csv(BufReader::new(f), batch, &tx),
+                Format::Zip => {
+                    let mut archive =
+                        zip::ZipArchive::new(f).map_err(|e| archive_error(&path, e))?;
+                    let mut csv = None;
+                    for i in 0..archive.len() {
+                        let entry = archive.by_index(i).map_err(|e| archive_error(&path, e))?;
+                        if !entry.is_dir() && entry.name().ends_with(".csv") {
+                            csv = Some(i);
+                            break;
+                        }
+                    }
+                    let i = csv.ok_or_else(|| archive_error(&path, "no csv file inside"))?;
+                    let entry = archive.by_index(i).map_err(|e| archive_error(&path, e))?;
+                    send_csv(BufReader::new(entry), batch, &tx)
+                }
+                Format::Parquet => send_parquet(f, columns, batch, &tx),
+            }
+        })();
+        if let Err(e) = res {
+            let _ = tx.blocking_send(Err(e));
+        }
+    });
+    rx
+}
+
+/// Skips blank lines and the header line newer archives start with.
+fn send_csv(
+    reader: impl BufRead,
+    batch: usize,
+    tx: &mpsc::Sender<Result<Vec<Record>>>,
+) -> Result<()> {
+    let mut buf = Vec::with_capacity(batch);
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim_end_matches('\r');
+        if line.is_empty() {
+            continue;
+        }
+        let fields: Record = line.split(',').map(str::to_string).collect();
+        if i == 0 && fields[0].parse::<i64>().is_err() {
+            continue;
+        }
+        buf.push(fields);
+        if buf.len() >= batch && tx.blocking_send(Ok(std::mem::take(&mut buf))).is_err() {
+            return Ok(());
+        }
+    }
+    if !buf.is_empty() {
+        let _ = tx.blocking_send(Ok(buf));
+    }
+    Ok(())
+}
+
+fn send_parquet(
+    f: File,
+    columns: &[&str],
+    batch: usize,
+    tx: &mpsc::Sender<Result<Vec<Record>>>,
+) -> Result<()> {
+    let df = ParquetReader::new(f).finish()?;
+    let columns = columns
+        .iter()
+        .map(|c| df.column(c)?.cast(&DataType::Utf8))
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    let columns = columns
+        .iter()
+        .map(|c| c.utf8())
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    let mut buf = Vec::with_capacity(batch);
+    for row in 0..df.height() {
+        buf.push(
+            columns
+                .iter()
+                .map(|c| c.get(row).unwrap_or_default().to_string())
+                .collect(),
+        );
+        if buf.len() >= batch && tx.blocking_send(Ok(std::mem::take(&mut buf))).is_err() {
+            return Ok(());
+        }
+    }
+    if !buf.is_empty() {
+        let _ = tx.blocking_send(Ok(buf));
+    }
+    Ok(())
+}
+
+fn field(fields: &[String], i: usize) -> std::result::Result<&str, String> {
+    fields
+        .get(i)
+        .map(String::as_str)
+        .ok_or_else(|| format!("missing column {}", i))
+}
+
+fn int_field(fields: &[String], i: usize) -> std::result::Result<i64, String> {
+    let v = field(fields, i)?;
+    v.parse().map_err(|_| format!("column {} is not an integer: {:?}", i, v))
+}
+
+fn decimal_field(fields: &[String], i: usize) -> std::result::Result<Decimal, String> {
+    let v = field(fields, i)?;
+    Decimal::from_str(v).map_err(|_| format!("column {} is not a decimal: {:?}", i, v))
+}
+
+fn bool_field(fields: &[String], i: usize) -> std::result::Result<bool, String> {
+    match field(fields, i)? {
+        "true" | "True" | "1" => Ok(true),
+        "false" | "False" | "0" => Ok(false),
+        v => Err(format!("column {} is not a bool: {:?}", i, v)),
+    }
+}
+
+/// Archive timestamps are in ms, except spot archives from 2025 on, which
+/// use µs.
+fn millis(ts: i64) -> i64 {
+    if ts >= 100_000_000_000_000 {
+        ts / 1000
+    } else {
+        ts
+    }
+}
+
+/// A row type that can be imported from archives.
+pub trait ArchiveRow: Sized + Send + 'static {
+    /// Archive columns in CSV order. Parquet files are read by these names.
+    const COLUMNS: &'static [&'static str];
+
+    fn archive_file(path: &Path) -> Option<ArchiveFile>;
+
+    /// `row` counts the data rows of the file from 1.
+    fn parse(
+        conf: &CsvConf,
+        period: &ImportPeriod,
+        row: usize,
+        fields: &[String],
+    ) -> std::result::Result<Self, String>;
+
+    /// Time in ms the row is filed under, expected within the file period.
+    fn period_time(&self) -> i64;
+
+    fn check(&self, validator: &mut EventValidator) -> Result<()>;
+}
+
+impl ArchiveRow for KlineEvent {
+    const COLUMNS: &'static [&'static str] = &[
+        "open_time",
+        "open",
+        "high",
+        "low",
+        "close",
+        "volume",
+        "close_time",
+        "quote_volume",
+        "count",
+        "taker_buy_volume",
+        "taker_buy_quote_volume",
+    ];
+
+    fn archive_file(path: &Path) -> Option<ArchiveFile> {
+        ArchiveFile::kline(path)
+    }
+
+    /// Archives carry no trade ids, so those stay 0.
+    fn parse(
+        conf: &CsvConf,
+        period: &ImportPeriod,
+        _row: usize,
+        fields: &[String],
+    ) -> std::result::Result<Self, String> {
+        let open_time = millis(int_field(fields, 0)?);
+        Ok(KlineEvent {
+            exchange: conf.exchange.clone(),
+            market: conf.market.clone(),
+            id: open_time,
+            event: EVENT_KLINE.to_string(),
+            symbol: period.symbol.clone(),
+            start_time: open_time,
+            end_time: millis(int_field(fields, 6)?),
+            interval: period.interval.clone(),
+            open: decimal_field(fields, 1)?,
+            high: decimal_field(fields, 2)?,
+            low: decimal_field(fields, 3)?,
+            close: decimal_field(fields, 4)?,
+            volume: decimal_field(fields, 5)?,
+            quote_volume: decimal_field(fields, 7)?,
+            trade_num: int_field(fields, 8)?,
+            active_buy_volume: decimal_field(fields, 9)?,
+            active_buy_quote_volume: decimal_field(fields, 10)?,
+            ..Default::default()
+        })
+    }
+
+    fn period_time(&self) -> i64 {
+        self.id
+    }
+
+    fn check(&self, validator: &mut EventValidator) -> Result<()> {
+        validator.check_archived_kline(self)
+    }
+}
+
+impl ArchiveRow for AggTrade {
+    const COLUMNS: &'static [&'static str] = &[
+        "agg_trade_id",
+        "price",
+        "quantity",
+        "first_trade_id",
+        "last_trade_id",
+        "transact_time",
+        "is_buyer_maker",
+    ];
+
+    fn archive_file(path: &Path) -> Option<ArchiveFile> {
+        ArchiveFile::agg_trade(path)
+    }
+
+    fn parse(
+        _conf: &CsvConf,
+        period: &ImportPeriod,
+        _row: usize,
+        fields: &[String],
+    ) -> std::result::Result<Self, String> {
+        let time = millis(int_field(fields, 5)?);
+        Ok(AggTrade {
+            time,
+            event: EVENT_AGG_TRADE.to_string(),
+            symbol: period.symbol.clone(),
+            agg_trade_id: int_field(fields, 0)?,
+            price: decimal_field(fields, 1)?,
+            quantity: decimal_field(fields, 2)?,
+            first_trade_id: int_field(fields, 3)?,
+            last_trade_id: int_field(fields, 4)?,
+            trade_time: time,
+            maker: bool_field(fields, 6)?,
+        })
+    }
+
+    fn period_time(&self) -> i64 {
+        self.trade_time
+    }
+
+    fn check(&self, validator: &mut EventValidator) -> Result<()> {
+        validator.check_agg_trade(self)
+    }
+}
+
+impl ArchiveRow for ForceOrderEvent {
+    const COLUMNS: &'static [&'static str] = &[
+        "time",
+        "side",
+        "order_type",
+        "time_in_force",
+        "original_quantity",
+        "price",
+        "average_price",
+        "order_status",
+        "last_fill_quantity",
+        "accumulated_fill_quantity",
+    ];
+
+    fn archive_file(path: &Path) -> Option<ArchiveFile> {
+        ArchiveFile::liquidation(path)
+    }
+
+    /// Snapshots have no order id. Ids of the liquidation tables are µs
+    /// timestamps, so the row number fills the sub-millisecond digits to keep
+    /// orders of the same millisecond apart.
+    fn parse(
+        conf: &CsvConf,
+        period: &ImportPeriod,
+        row: usize,
+        fields: &[String],
+    ) -> std::result::Result<Self, String> {
+        let time = millis(int_field(fields, 0)?);
+        Ok(ForceOrderEvent {
+            exchange: conf.exchange.clone(),
+            market: conf.market.clone(),
+            id: time * 1000 + (row % 1000) as i64,
+            event: EVENT_FORCE_ORDER.to_string(),
+            symbol: period.symbol.clone(),
+            side: field(fields, 1)?.to_string(),
+            order_type: field(fields, 2)?.to_string(),
+            time_in_force: field(fields, 3)?.to_string(),
+            orig_quantity: decimal_field(fields, 4)?,
+            price: decimal_field(fields, 5)?,
+            avg_price: decimal_field(fields, 6)?,
+            order_status: field(fields, 7)?.to_string(),
+            last_filled_qty: decimal_field(fields, 8)?,
+            accumulated_filled_qty: decimal_field(fields, 9)?,
+            trade_time: time,
+        })
+    }
+
+    fn period_time(&self) -> i64 {
+        self.trade_time
+    }
+
+    fn check(&self, validator: &mut EventValidator) -> Result<()> {
+        validator.check_force_order(self)
+    }
+}
+
+/// Where imported rows of type `T` are written.
+#[async_trait]
+pub trait ArchiveStore<T> {
+    /// Removes rows of `period` an interrupted import may have left behind,
+    /// where the table would not collapse them itself.
+    async fn clear(&self, _conf: &CsvConf, _period: &ImportPeriod) -> Result<()> {
+        Ok(())
+    }
+
+    async fn store(&self, conf: &CsvConf, period: &ImportPeriod, rows: Vec<T>) -> Result<()>;
+}
+
+#[async_trait]
+impl<K: KlineRepo + Sync> ArchiveStore<KlineEvent> for K {
+    async fn clear(&self, _conf: &CsvConf, period: &ImportPeriod) -> Result<()> {
+        let table = kline_table_name(&period.symbol, &period.interval);
+        self.delete_klines(&table, period.start_time, period.end_time)
+            .await
+    }
+
+    async fn store(&self, _conf: &CsvConf, period: &ImportPeriod, rows: Vec<KlineEvent>) -> Result<()> {
+        let table = kline_table_name(&period.symbol, &period.interval);
+        self.insert_klines(&table, rows).await
+    }
+}
+
+#[async_trait]
+impl<A: AggTradeRepo + Sync> ArchiveStore<AggTrade> for A {
+    async fn store(&self, conf: &CsvConf, period: &ImportPeriod, rows: Vec<AggTrade>) -> Result<()> {
+        let table = aggtrade_table_name(&conf.exchange, &conf.market, &period.symbol);
+        self.insert_agg_trades(&table, rows).await
+    }
+}
+
+#[async_trait]
+impl<F: ForceOrderRepo + Sync> ArchiveStore<ForceOrderEvent> for F {
+    async fn clear(&self, conf: &CsvConf, period: &ImportPeriod) -> Result<()> {
+        let table = liquidation_table_name(&conf.exchange, &conf.market, &period.symbol);
+        self.delete_force_orders(&table, period.start_time, period.end_time)
+            .await
+    }
+
+    async fn store(
+        &self,
+        conf: &CsvConf,
+        period: &ImportPeriod,
+        rows: Vec<ForceOrderEvent>,
+    ) -> Result<()> {
+        let table = liquidation_table_name(&conf.exchange, &conf.market, &period.symbol);
+        self.insert_force_orders(&table, rows).await
+    }
+}
+
+/// Imports archives into the per-symbol tables, which must already exist
+/// (see `ClickhouseQuery::ensure_tables`).
+pub struct ArchiveImporter<J, S> {
+    jobs: J,
+    store: S,
+    conf: CsvConf,
+}
+
+impl<J: ImportJobRepo + Sync, S: Sync> ArchiveImporter<J, S> {
+    pub fn new(jobs: J, store: S, conf: CsvConf) -> Self {
+        ArchiveImporter { jobs, store, conf }
+    }
+
+    /// Imports every `T` archive under `conf.path` that is not recorded yet.
+    /// A file that fails is logged and retried on the next run.
+    pub async fn import_all<T: ArchiveRow>(&self) -> Result<ImportReport>
+    where
+        S: ArchiveStore<T>,
+    {
+        let files = scan(Path::new(&self.conf.path), T::archive_file).await?;
+        let mut report = ImportReport::default();
+        for file in files.iter() {
+            match self.import_file::<T>(file).await {
+                Ok(r) => report += r,
+                Err(e) => {
+                    log::error!("import {}: {}", file.path.display(), e);
+                    report.failed += 1;
+                }
+            }
+        }
+        log::info!("import {}: {:?}", self.conf.path, report);
+        Ok(report)
+    }
+
+    /// Rows that fail validation or fall outside the file's period are
+    /// logged and dropped; a malformed row fails the whole file.
+    pub async fn import_file<T: ArchiveRow>(&self, file: &ArchiveFile) -> Result<ImportReport>
+    where
+        S: ArchiveStore<T>,
+    {
+        let mut report = ImportReport::default();
+        let period = &ImportPeriod {
+            exchange: self.conf.exchange.clone(),
+            market: self.conf.market.clone(),
+            ..file.period.clone()
+        };
+        if self.jobs.import_covered(period).await? {
+            report.skipped = 1;
+            return Ok(report);
+        }
+        self.store.clear(&self.conf, period).await?;
+        let mut validator = EventValidator::new();
+        let mut records = read_records(file, T::COLUMNS, self.conf.batch_size.max(1));
+        let mut row_no = 0;
+        while let Some(batch) = records.recv().await {
+            let mut rows = vec![];
+            for fields in batch? {
+                row_no += 1;
+                let row = T::parse(&self.conf, period, row_no, &fields)
+                    .map_err(|e| archive_error(&file.path, format!("row {}: {}", row_no, e)))?;
+                let time = row.period_time();
+                let checked = if time < period.start_time || time >= period.end_time {
+                    Err(format!("time {} is outside the file period", time))
+                } else {
+                    row.check(&mut validator).map_err(|e| e.message)
+                };
+                match checked {
+                    Ok(()) => rows.push(row),
+                    Err(e) => {
+                        log::warn!("import {} row {}: {}", file.path.display(), row_no, e);
+                        report.rejected += 1;
+                    }
+                }
+            }
+            report.rows += rows.len();
+            self.store.store(&self.conf, period, rows).await?;
+        }
+        self.jobs.record_import(period).await?;
+        report.files = 1;
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn record(line: &str) -> Record {
+        line.split(',').map(str::to_string).collect()
+    }
+
+    #[test]
+    fn test_archive_file() {
+        let file = ArchiveFile::kline(Path::new("/data/klines/BTCUSDT-1m-2024-02.zip")).unwrap();
+        assert_eq!(file.period.symbol, "BTCUSDT");
+        assert_eq!(file.period.interval, "1m");
+        assert_eq!(file.period.start_time, 1_706_745_600_000);
+        assert_eq!(file.period.end_time - file.period.start_time, 29 * 86_400_000);
+        assert_eq!(file.format(), Format::Zip);
+
+        let file = ArchiveFile::kline(Path::new("ETHUSDT-4h-2024-02-29.csv")).unwrap();
+        assert_eq!(file.period.end_time - file.period.start_time, 86_400_000);
+        assert_eq!(file.format(), Format::Csv);
+
+        assert_eq!(ArchiveFile::kline(Path::new("BTCUSDT-1m-2024-02.zip.CHECKSUM")), None);
+        assert_eq!(ArchiveFile::kline(Path::new("BTCUSDT-1x-2024-02.zip")), None);
+        assert_eq!(ArchiveFile::kline(Path::new("BTCUSDT-1m-2024-13.zip")), None);
+
+        let file = ArchiveFile::agg_trade(Path::new("BTCUSDT-aggTrades-2024-02-01.parquet")).unwrap();
+        assert_eq!(file.period.event, EVENT_AGG_TRADE);
+        assert_eq!(file.period.interval, "");
+        assert_eq!(file.format(), Format::Parquet);
+        assert_eq!(ArchiveFile::agg_trade(Path::new("BTCUSDT-1m-2024-02.zip")), None);
+        assert!(ArchiveFile::liquidation(Path::new("BTCUSDT-liquidationSnapshot-2024-02-01.zip")).is_some());
+        assert_eq!(ArchiveFile::kline(Path::new("BTCUSDT-aggTrades-2024-02.zip")), None);
+    }
+
+    #[test]
+    fn test_parse_kline() {
+        let conf = CsvConf::default();
+        let file = ArchiveFile::kline(Path::new("BTCUSDT-1m-2025-01-01.csv")).unwrap();
+        let fields = record(
+            "1735689600000000,93576.00,93610.93,93537.50,93610.93,8.21827,\
+             1735689659999999,768978.05,1287,3.89,364039.05,0",
+        );
+        let kline = KlineEvent::parse(&conf, &file.period, 1, &fields).unwrap();
+        assert_eq!(kline.id, 1_735_689_600_000);
+        assert_eq!(kline.end_time, 1_735_689_659_999);
+        assert_eq!(kline.close, dec!(93610.93));
+        assert_eq!(kline.trade_num, 1287);
+        assert_eq!(kline.active_buy_quote_volume, dec!(364039.05));
+        assert!(kline.check(&mut EventValidator::new()).is_ok());
+
+        let fields = record("1735689600000,93576.00,x");
+        assert!(KlineEvent::parse(&conf, &file.period, 1, &fields).is_err());
+    }
+
+    #[test]
+    fn test_parse_agg_trade_and_liquidation() {
+        let conf = CsvConf::default();
+        let file = ArchiveFile::agg_trade(Path::new("ETHUSDT-aggTrades-2024-01-01.csv")).unwrap();
+        let fields = record("1021433957,2281.87,0.015,2461331880,2461331881,1704067200012,true");
+        let trade = AggTrade::parse(&conf, &file.period, 1, &fields).unwrap();
+        assert_eq!(trade.agg_trade_id, 1_021_433_957);
+        assert_eq!(trade.quantity, dec!(0.015));
+        assert_eq!(trade.trade_time, 1_704_067_200_012);
+        assert!(trade.maker);
+        assert!(trade.check(&mut EventValidator::new()).is_ok());
+
+        let file =
+            ArchiveFile::liquidation(Path::new("ETHUSDT-liquidationSnapshot-2024-01-01.csv")).unwrap();
+        let fields = record("1704067200012,SELL,LIMIT,IOC,0.5,2270.10,2275.33,FILLED,0.5,0.5");
+        let order = ForceOrderEvent::parse(&conf, &file.period, 1001, &fields).unwrap();
+        assert_eq!(order.id, 1_704_067_200_012_001);
+        assert_eq!(order.side, "SELL");
+        assert_eq!(order.avg_price, dec!(2275.33));
+        assert!(order.check(&mut EventValidator::new()).is_ok());
+    }
+}
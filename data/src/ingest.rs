@@ -0,0 +1,300 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use ecode::{Reason, Result, Status};
+use rust_decimal::Decimal;
+use validator::Validate;
+
+use crate::{
+    clickhouse::kline_table,
+    codec::DomainEvent,
+    domain::{AggTrade, ForceOrderEvent, KlineEvent},
+    entity::cex_market_symbol,
+    DeadLetterRepo, KlineRepo,
+};
+
+fn reject(message: impl Into<String>) -> Status {
+    Status::new(400, Reason::InvalidEvent, message)
+}
+
+/// Checks market invariants of incoming events. Remembers the last accepted
+/// id of every stream so replays and out of order events are caught.
+#[derive(Debug, Default)]
+pub struct EventValidator {
+    last_ids: HashMap<String, i64>,
+}
+
+impl EventValidator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `symbol` is the row the kline was subscribed for. A kline may repeat the
+    /// last id while its candle is still open, but never go back.
+    pub fn check_kline(&mut self, symbol: &cex_market_symbol::Model, k: &KlineEvent) -> Result<()> {
+        k.validate().map_err(|e| reject(format!("kline {}: {}", k.id, e)))?;
+        if !k.symbol.eq_ignore_ascii_case(&symbol.symbol) || k.interval != symbol.interval {
+            return Err(reject(format!(
+                "kline {}: {} {} does not match {} {}",
+                k.id, k.symbol, k.interval, symbol.symbol, symbol.interval
+            )));
+        }
+        self.check_candle(k)
+    }
+
+    /// `check_kline` for candles from Binance's public archives, which carry
+    /// no trade ids.
+    pub fn check_archived_kline(&mut self, k: &KlineEvent) -> Result<()> {
+        if let Err(mut e) = k.validate() {
+            e.errors_mut().remove("first_trade_id");
+            e.errors_mut().remove("last_trade_id");
+            if !e.is_empty() {
+                return Err(reject(format!("kline {}: {}", k.id, e)));
+            }
+        }
+        self.check_candle(k)
+    }
+
+    fn check_candle(&mut self, k: &KlineEvent) -> Result<()> {
+        if k.start_time >= k.end_time {
+            return Err(reject(format!(
+                "kline {}: start_time {} is not before end_time {}",
+                k.id, k.start_time, k.end_time
+            )));
+        }
+        if k.low > k.open.min(k.close) || k.high < k.open.max(k.close) {
+            return Err(reject(format!(
+                "kline {}: open {} and close {} are not within low {} and high {}",
+                k.id, k.open, k.close, k.low, k.high
+            )));
+        }
+        if k.active_buy_volume > k.volume || k.active_buy_quote_volume > k.quote_volume {
+            return Err(reject(format!("kline {}: active buy volume exceeds volume", k.id)));
+        }
+        let key = format!("kline:{}:{}:{}:{}", k.exchange, k.market, k.symbol, k.interval);
+        self.advance(key, k.id, true)
+            .map_err(|last| reject(format!("kline {}: id is behind {}", k.id, last)))
+    }
+
+    pub fn check_agg_trade(&mut self, t: &AggTrade) -> Result<()> {
+        t.validate()
+            .map_err(|e| reject(format!("agg trade {}: {}", t.agg_trade_id, e)))?;
+        if t.price <= Decimal::ZERO || t.quantity <= Decimal::ZERO {
+            return Err(reject(format!(
+                "agg trade {}: price {} and quantity {} must be positive",
+                t.agg_trade_id, t.price, t.quantity
+            )));
+        }
+        if t.first_trade_id > t.last_trade_id {
+            return Err(reject(format!(
+                "agg trade {}: first_trade_id {} is after last_trade_id {}",
+                t.agg_trade_id, t.first_trade_id, t.last_trade_id
+            )));
+        }
+        let key = format!("agg_trade:{}", t.symbol);
+        self.advance(key, t.agg_trade_id, false).map_err(|last| {
+            reject(format!("agg trade {}: id is not after {}", t.agg_trade_id, last))
+        })
+    }
+
+    pub fn check_force_order(&self, o: &ForceOrderEvent) -> Result<()> {
+        o.validate()
+            .map_err(|e| reject(format!("force order {}: {}", o.id, e)))?;
+        if o.side != "BUY" && o.side != "SELL" {
+            return Err(reject(format!("force order {}: unknown side {}", o.id, o.side)));
+        }
+        if o.orig_quantity <= Decimal::ZERO || o.price <= Decimal::ZERO {
+            return Err(reject(format!(
+                "force order {}: price {} and orig_quantity {} must be positive",
+                o.id, o.price, o.orig_quantity
+            )));
+        }
+        if o.last_filled_qty > o.accumulated_filled_qty
+            || o.accumulated_filled_qty > o.orig_quantity
+        {
+            return Err(reject(format!(
+                "force order {}: filled {} / {} exceeds orig_quantity {}",
+                o.id, o.last_filled_qty, o.accumulated_filled_qty, o.orig_quantity
+            )));
+        }
+        Ok(())
+    }
+
+    /// Records `id` as the last one of `key`, or returns the last id when
+    /// `id` would move backwards.
+    fn advance(&mut self, key: String, id: i64, allow_equal: bool) -> std::result::Result<(), i64> {
+        if let Some(last) = self.last_ids.get(&key) {
+            if id < *last || (id == *last && !allow_equal) {
+                return Err(*last);
+            }
+        }
+        self.last_ids.insert(key, id);
+        Ok(())
+    }
+}
+
+/// Validates klines before they reach `KlineRepo::insert_kline`; rejected
+/// ones go to the dead-letter stream with the reason instead.
+pub struct KlineIngest<K, D> {
+    klines: K,
+    dead: D,
+    validator: Mutex<EventValidator>,
+}
+
+impl<K, D> KlineIngest<K, D>
+where
+    K: KlineRepo + Sync,
+    D: DeadLetterRepo + Sync,
+{
+    pub fn new(klines: K, dead: D) -> Self {
+        KlineIngest {
+            klines,
+            dead,
+            validator: Mutex::new(EventValidator::new()),
+        }
+    }
+
+    /// Returns whether the kline was stored. Errors are storage failures
+    /// only, never validation failures.
+    pub async fn ingest(&self, symbol: &cex_market_symbol::Model, kline: KlineEvent) -> Result<bool> {
+        let checked = self.validator.lock().unwrap().check_kline(symbol, &kline);
+        if let Err(e) = checked {
+            log::warn!("dead letter {}", e);
+            self.dead
+                .dead_letter(&DomainEvent::Kline(kline), &e.message)
+                .await?;
+            return Ok(false);
+        }
+        self.klines.insert_kline(&kline_table(symbol), kline).await?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn symbol() -> cex_market_symbol::Model {
+        cex_market_symbol::Model {
+            id: 1,
+            exchange: "binance".to_string(),
+            market: "futures".to_string(),
+            symbol: "ETHUSDT".to_string(),
+            interval: "1m".to_string(),
+            base: "ETH".to_string(),
+            quote: "USDT".to_string(),
+            status: 1,
+            created_at: Default::default(),
+            updated_at: Default::default(),
+            deleted_at: None,
+        }
+    }
+
+    fn kline(id: i64) -> KlineEvent {
+        KlineEvent {
+            exchange: "binance".to_string(),
+            market: "futures".to_string(),
+            id,
+            event: "kline".to_string(),
+            symbol: "ETHUSDT".to_string(),
+            start_time: id,
+            end_time: id + 59_999,
+            interval: "1m".to_string(),
+            first_trade_id: 1,
+            last_trade_id: 2,
+            open: dec!(100),
+            close: dec!(101),
+            high: dec!(102),
+            low: dec!(99),
+            volume: dec!(10),
+            quote_volume: dec!(1000),
+            active_buy_volume: dec!(4),
+            active_buy_quote_volume: dec!(400),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_check_kline() {
+        let mut v = EventValidator::new();
+        let s = symbol();
+        assert!(v.check_kline(&s, &kline(120_000)).is_ok());
+        assert!(v.check_kline(&s, &kline(120_000)).is_ok());
+        assert!(v.check_kline(&s, &kline(60_000)).is_err());
+
+        let mut k = kline(180_000);
+        k.high = dec!(100.5);
+        assert!(v.check_kline(&s, &k).is_err());
+
+        let mut k = kline(180_000);
+        k.volume = dec!(-1);
+        assert_eq!(v.check_kline(&s, &k).unwrap_err().reason, Reason::InvalidEvent);
+
+        let mut k = kline(180_000);
+        k.interval = "5m".to_string();
+        assert!(v.check_kline(&s, &k).is_err());
+
+        let mut k = kline(180_000);
+        k.end_time = k.start_time;
+        assert!(v.check_kline(&s, &k).is_err());
+
+        assert!(v.check_kline(&s, &kline(180_000)).is_ok());
+
+        let mut k = kline(240_000);
+        k.first_trade_id = 0;
+        k.last_trade_id = 0;
+        assert!(v.check_kline(&s, &k).is_err());
+        assert!(v.check_archived_kline(&k).is_ok());
+        k.id = 300_000;
+        k.volume = dec!(-1);
+        assert!(v.check_archived_kline(&k).is_err());
+    }
+
+    #[test]
+    fn test_check_agg_trade() {
+        let mut v = EventValidator::new();
+        let trade = AggTrade {
+            time: 1,
+            event: "aggTrade".to_string(),
+            symbol: "ETHUSDT".to_string(),
+            agg_trade_id: 5,
+            price: dec!(100),
+            quantity: dec!(1),
+            first_trade_id: 1,
+            last_trade_id: 3,
+            trade_time: 1,
+            maker: false,
+        };
+        assert!(v.check_agg_trade(&trade).is_ok());
+        assert!(v.check_agg_trade(&trade).is_err());
+        let mut t = trade.clone();
+        t.agg_trade_id = 6;
+        t.quantity = Decimal::ZERO;
+        assert!(v.check_agg_trade(&t).is_err());
+    }
+
+    #[test]
+    fn test_check_force_order() {
+        let v = EventValidator::new();
+        let mut o = ForceOrderEvent {
+            exchange: "binance".to_string(),
+            market: "futures".to_string(),
+            id: 1,
+            event: "forceOrder".to_string(),
+            symbol: "ETHUSDT".to_string(),
+            side: "SELL".to_string(),
+            order_type: "LIMIT".to_string(),
+            time_in_force: "IOC".to_string(),
+            orig_quantity: dec!(2),
+            price: dec!(100),
+            avg_price: dec!(100),
+            order_status: "FILLED".to_string(),
+            last_filled_qty: dec!(1),
+            accumulated_filled_qty: dec!(2),
+            trade_time: 1,
+        };
+        assert!(v.check_force_order(&o).is_ok());
+        o.accumulated_filled_qty = dec!(3);
+        assert!(v.check_force_order(&o).is_err());
+    }
+}
@@ -0,0 +1,471 @@
+//! Read-through cache for the latest klines of a table, in front of any
+//! `KlineRepo`. Entries expire when their newest candle closes and are
+//! dropped after every insert into their table made through the same
+//! `CachedKlineRepo`; writes from other processes show up once the entry
+//! expires.
+
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use ecode::Result;
+use redis::AsyncCommands;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::{cache::RedisQuery, domain::interval_duration, domain::KlineEvent, KlineRepo};
+
+/// Latest `limit` klines of a table, newest first as `fetch_kline_limit` returns them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CachedKlines {
+    pub limit: u32,
+    pub klines: Vec<KlineEvent>,
+}
+
+/// Storage behind `CachedKlineRepo`, one entry per table.
+#[async_trait]
+pub trait KlineCache {
+    async fn get(&self, table: &str) -> Result<Option<CachedKlines>>;
+    async fn put(&self, table: &str, entry: &CachedKlines, ttl: Duration) -> Result<()>;
+    async fn invalidate(&self, table: &str) -> Result<()>;
+}
+
+fn cache_key(table: &str) -> String {
+    format!("kline_cache:{}", table)
+}
+
+#[async_trait]
+impl KlineCache for RedisQuery {
+    async fn get(&self, table: &str) -> Result<Option<CachedKlines>> {
+        let mut conn = self.db.get_async_connection().await?;
+        let raw: Option<String> = conn.get(cache_key(table)).await?;
+        // An entry that no longer decodes, e.g. after a schema change, is a miss.
+        Ok(raw.and_then(|raw| serde_json::from_str(&raw).ok()))
+    }
+
+    async fn put(&self, table: &str, entry: &CachedKlines, ttl: Duration) -> Result<()> {
+        let raw = serde_json::to_string(entry).map_err(|e| {
+            ecode::Status::new(500, ecode::Reason::InvalidEvent, e.to_string())
+        })?;
+        let mut conn = self.db.get_async_connection().await?;
+        let _: () = redis::cmd("SET")
+            .arg(cache_key(table))
+            .arg(raw)
+            .arg("PX")
+            .arg(ttl.as_millis() as u64)
+            .query_async(&mut conn)
+            .await?;
+        Ok(())
+    }
+
+    async fn invalidate(&self, table: &str) -> Result<()> {
+        let mut conn = self.db.get_async_connection().await?;
+        let _: i64 = conn.del(cache_key(table)).await?;
+        Ok(())
+    }
+}
+
+/// In-process LRU of at most `capacity` tables.
+pub struct MemoryKlineCache {
+    capacity: usize,
+    entries: Mutex<MemoryEntries>,
+}
+
+#[derive(Default)]
+struct MemoryEntries {
+    tick: u64,
+    /// table -> (expires at, last used tick, entry)
+    map: HashMap<String, (Instant, u64, CachedKlines)>,
+}
+
+impl MemoryKlineCache {
+    pub fn new(capacity: usize) -> Self {
+        MemoryKlineCache {
+            capacity: capacity.max(1),
+            entries: Mutex::new(MemoryEntries::default()),
+        }
+    }
+}
+
+#[async_trait]
+impl KlineCache for MemoryKlineCache {
+    async fn get(&self, table: &str) -> Result<Option<CachedKlines>> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.tick += 1;
+        let tick = entries.tick;
+        match entries.map.get_mut(table) {
+            Some((expires, _, _)) if *expires <= Instant::now() => {
+                entries.map.remove(table);
+                Ok(None)
+            }
+            Some((_, used, entry)) => {
+                *used = tick;
+                Ok(Some(entry.clone()))
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn put(&self, table: &str, entry: &CachedKlines, ttl: Duration) -> Result<()> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.tick += 1;
+        let tick = entries.tick;
+        if !entries.map.contains_key(table) && entries.map.len() >= self.capacity {
+            let lru = entries
+                .map
+                .iter()
+                .min_by_key(|(_, (_, used, _))| *used)
+                .map(|(k, _)| k.clone());
+            if let Some(lru) = lru {
+                entries.map.remove(&lru);
+            }
+        }
+        entries
+            .map
+            .insert(table.to_string(), (Instant::now() + ttl, tick, entry.clone()));
+        Ok(())
+    }
+
+    async fn invalidate(&self, table: &str) -> Result<()> {
+        self.entries.lock().unwrap().map.remove(table);
+        Ok(())
+    }
+}
+
+/// Time left until the candle open at `now` closes, for tables named
+/// `kline_{symbol}_{interval}`. `None` when the interval is unknown.
+fn ttl_until_close(table: &str, now: DateTime<Utc>) -> Option<Duration> {
+    let interval = interval_duration(table.rsplit('_').next()?)?.num_milliseconds();
+    let left = interval - now.timestamp_millis().rem_euclid(interval);
+    Some(Duration::from_millis(left.max(1) as u64))
+}
+
+/// `KlineRepo` serving `fetch_kline_limit` from `cache`; everything else goes
+/// straight to `inner`. Cache failures are logged and fall back to `inner`.
+pub struct CachedKlineRepo<K, C> {
+    inner: K,
+    cache: C,
+    /// Writes per table through this repo, so a fetch can tell that one
+    /// finished while it was reading.
+    generations: Mutex<HashMap<String, u64>>,
+}
+
+impl<K, C> CachedKlineRepo<K, C> {
+    pub fn new(inner: K, cache: C) -> Self {
+        CachedKlineRepo {
+            inner,
+            cache,
+            generations: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn generation(&self, table: &str) -> u64 {
+        self.generations.lock().unwrap().get(table).copied().unwrap_or(0)
+    }
+}
+
+#[async_trait]
+impl<K, C> KlineRepo for CachedKlineRepo<K, C>
+where
+    K: KlineRepo + Sync,
+    C: KlineCache + Sync,
+{
+    async fn fetch_kline_limit(&self, table: &str, limit: u32) -> Result<Vec<KlineEvent>> {
+        let Some(ttl) = ttl_until_close(table, Utc::now()) else {
+            return self.inner.fetch_kline_limit(table, limit).await;
+        };
+        match self.cache.get(table).await {
+            Ok(Some(entry)) if entry.limit >= limit => {
+                return Ok(entry.klines.into_iter().take(limit as usize).collect());
+            }
+            Ok(_) => {}
+            Err(e) => log::warn!("kline cache get {}: {}", table, e),
+        }
+        let generation = self.generation(table);
+        let klines = self.inner.fetch_kline_limit(table, limit).await?;
+        let entry = CachedKlines { limit, klines };
+        if let Err(e) = self.cache.put(table, &entry, ttl).await {
+            log::warn!("kline cache put {}: {}", table, e);
+        } else if self.generation(table) != generation {
+            // A write finished while we read, and its invalidate may have run
+            // before our put: the entry may predate the write.
+            self.drop_cached(table).await;
+        }
+        Ok(entry.klines)
+    }
+
+    async fn fetch_kline_time_limit(
+        &self,
+        table: &str,
+        start: &DateTime<Utc>,
+        to: &DateTime<Utc>,
+    ) -> Result<Vec<KlineEvent>> {
+        self.inner.fetch_kline_time_limit(table, start, to).await
+    }
+
+    async fn fetch_kline_range(
+        &self,
+        table: &str,
+        start: &DateTime<Utc>,
+        to: &DateTime<Utc>,
+    ) -> Result<Vec<KlineEvent>> {
+        self.inner.fetch_kline_range(table, start, to).await
+    }
+
+    async fn fetch_kline_time_limit_open(
+        &self,
+        table: &str,
+        start: &DateTime<Utc>,
+        to: &DateTime<Utc>,
+    ) -> Result<Decimal> {
+        self.inner.fetch_kline_time_limit_open(table, start, to).await
+    }
+
+    async fn fetch_kline_time_limit_high(
+        &self,
+        table: &str,
+        start: &DateTime<Utc>,
+        to: &DateTime<Utc>,
+    ) -> Result<Decimal> {
+        self.inner.fetch_kline_time_limit_high(table, start, to).await
+    }
+
+    async fn fetch_kline_time_limit_low(
+        &self,
+        table: &str,
+        start: &DateTime<Utc>,
+        to: &DateTime<Utc>,
+    ) -> Result<Decimal> {
+        self.inner.fetch_kline_time_limit_low(table, start, to).await
+    }
+
+    async fn fetch_kline_time_limit_close(
+        &self,
+        table: &str,
+        start: &DateTime<Utc>,
+        to: &DateTime<Utc>,
+    ) -> Result<Decimal> {
+        self.inner.fetch_kline_time_limit_close(table, start, to).await
+    }
+
+    async fn insert_kline(&self, table: &str, kline: KlineEvent) -> Result<()> {
+        self.inner.insert_kline(table, kline).await?;
+        self.invalidate(table).await;
+        Ok(())
+    }
+
+    async fn insert_klines(&self, table: &str, klines: Vec<KlineEvent>) -> Result<()> {
+        self.inner.insert_klines(table, klines).await?;
+        self.invalidate(table).await;
+        Ok(())
+    }
+
+    async fn delete_klines(&self, table: &str, start: i64, end: i64) -> Result<()> {
+        self.inner.delete_klines(table, start, end).await?;
+        self.invalidate(table).await;
+        Ok(())
+    }
+}
+
+impl<K, C: KlineCache> CachedKlineRepo<K, C> {
+    /// Runs after every write. Dropping the entry alone is not enough: a
+    /// fetch that read the old rows before the write may put them back after
+    /// this, so the generation bump makes that fetch drop them again.
+    async fn invalidate(&self, table: &str) {
+        *self
+            .generations
+            .lock()
+            .unwrap()
+            .entry(table.to_string())
+            .or_default() += 1;
+        self.drop_cached(table).await;
+    }
+
+    async fn drop_cached(&self, table: &str) {
+        if let Err(e) = self.cache.invalidate(table).await {
+            log::warn!("kline cache invalidate {}: {}", table, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use tokio::sync::Notify;
+
+    /// Newest first, like ClickHouse returns them.
+    #[derive(Default)]
+    struct Klines {
+        rows: Mutex<Vec<KlineEvent>>,
+        fetches: AtomicUsize,
+        bulk_inserts: AtomicUsize,
+        /// When set, the next fetch waits for `gate` after reading its rows.
+        hold: AtomicBool,
+        gate: Notify,
+    }
+
+    #[async_trait]
+    impl KlineRepo for Klines {
+        async fn fetch_kline_limit(&self, _table: &str, limit: u32) -> Result<Vec<KlineEvent>> {
+            self.fetches.fetch_add(1, Ordering::SeqCst);
+            let rows: Vec<_> = {
+                let rows = self.rows.lock().unwrap();
+                rows.iter().take(limit as usize).cloned().collect()
+            };
+            if self.hold.swap(false, Ordering::SeqCst) {
+                self.gate.notified().await;
+            }
+            Ok(rows)
+        }
+
+        async fn fetch_kline_time_limit(
+            &self,
+            _: &str,
+            _: &DateTime<Utc>,
+            _: &DateTime<Utc>,
+        ) -> Result<Vec<KlineEvent>> {
+            Ok(Default::default())
+        }
+
+        async fn fetch_kline_range(
+            &self,
+            _: &str,
+            _: &DateTime<Utc>,
+            _: &DateTime<Utc>,
+        ) -> Result<Vec<KlineEvent>> {
+            Ok(Default::default())
+        }
+
+        async fn fetch_kline_time_limit_open(
+            &self,
+            _: &str,
+            _: &DateTime<Utc>,
+            _: &DateTime<Utc>,
+        ) -> Result<Decimal> {
+            Ok(Default::default())
+        }
+
+        async fn fetch_kline_time_limit_high(
+            &self,
+            _: &str,
+            _: &DateTime<Utc>,
+            _: &DateTime<Utc>,
+        ) -> Result<Decimal> {
+            Ok(Default::default())
+        }
+
+        async fn fetch_kline_time_limit_low(
+            &self,
+            _: &str,
+            _: &DateTime<Utc>,
+            _: &DateTime<Utc>,
+        ) -> Result<Decimal> {
+            Ok(Default::default())
+        }
+
+        async fn fetch_kline_time_limit_close(
+            &self,
+            _: &str,
+            _: &DateTime<Utc>,
+            _: &DateTime<Utc>,
+        ) -> Result<Decimal> {
+            Ok(Default::default())
+        }
+
+        async fn insert_kline(&self, _table: &str, kline: KlineEvent) -> Result<()> {
+            let mut rows = self.rows.lock().unwrap();
+            rows.retain(|k| k.id != kline.id);
+            rows.insert(0, kline);
+            Ok(())
+        }
+
+        async fn insert_klines(&self, table: &str, klines: Vec<KlineEvent>) -> Result<()> {
+            self.bulk_inserts.fetch_add(1, Ordering::SeqCst);
+            for kline in klines {
+                self.insert_kline(table, kline).await?;
+            }
+            Ok(())
+        }
+    
+        async fn delete_klines(&self, _table: &str, start: i64, end: i64) -> Result<()> {
+            let mut rows = self.rows.lock().unwrap();
+            rows.retain(|k| k.id < start || k.id >= end);
+            Ok(())
+        }
+    }
+
+    fn kline(id: i64) -> KlineEvent {
+        KlineEvent {
+            id,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_ttl_until_close() {
+        let now = Utc.timestamp_millis_opt(90_000).unwrap();
+        assert_eq!(ttl_until_close("kline_ethusdt_1m", now), Some(Duration::from_secs(30)));
+        assert_eq!(ttl_until_close("kline_ethusdt", now), None);
+    }
+
+    #[tokio::test]
+    async fn test_read_through() {
+        let repo = CachedKlineRepo::new(Klines::default(), MemoryKlineCache::new(8));
+        let table = "kline_ethusdt_1d";
+        repo.insert_kline(table, kline(1)).await.unwrap();
+        repo.insert_kline(table, kline(2)).await.unwrap();
+
+        assert_eq!(repo.fetch_kline_limit(table, 2).await.unwrap(), vec![kline(2), kline(1)]);
+        assert_eq!(repo.fetch_kline_limit(table, 1).await.unwrap(), vec![kline(2)]);
+        assert_eq!(repo.inner.fetches.load(Ordering::SeqCst), 1);
+
+        repo.insert_kline(table, kline(3)).await.unwrap();
+        assert_eq!(repo.fetch_kline_limit(table, 1).await.unwrap(), vec![kline(3)]);
+        assert_eq!(repo.inner.fetches.load(Ordering::SeqCst), 2);
+
+        repo.insert_klines(table, vec![kline(4), kline(5)]).await.unwrap();
+        assert_eq!(repo.inner.bulk_inserts.load(Ordering::SeqCst), 1);
+        assert_eq!(repo.fetch_kline_limit(table, 1).await.unwrap(), vec![kline(5)]);
+    }
+
+    #[tokio::test]
+    async fn test_insert_during_fetch() {
+        let repo = CachedKlineRepo::new(Klines::default(), MemoryKlineCache::new(8));
+        let table = "kline_ethusdt_1w";
+        repo.insert_kline(table, kline(1)).await.unwrap();
+
+        // the fetch reads kline 1, the insert and its invalidate complete,
+        // then the fetch puts its stale window
+        repo.inner.hold.store(true, Ordering::SeqCst);
+        let (stale, _) = tokio::join!(repo.fetch_kline_limit(table, 1), async {
+            repo.insert_kline(table, kline(2)).await.unwrap();
+            repo.inner.gate.notify_one();
+        });
+        assert_eq!(stale.unwrap(), vec![kline(1)]);
+        assert_eq!(repo.fetch_kline_limit(table, 1).await.unwrap(), vec![kline(2)]);
+    }
+
+    #[tokio::test]
+    async fn test_memory_lru() {
+        let cache = MemoryKlineCache::new(2);
+        let entry = CachedKlines {
+            limit: 1,
+            klines: vec![kline(1)],
+        };
+        let ttl = Duration::from_secs(60);
+        cache.put("a", &entry, ttl).await.unwrap();
+        cache.put("b", &entry, ttl).await.unwrap();
+        cache.get("a").await.unwrap();
+        cache.put("c", &entry, ttl).await.unwrap();
+        assert!(cache.get("a").await.unwrap().is_some());
+        assert!(cache.get("b").await.unwrap().is_none());
+        cache.put("d", &entry, Duration::ZERO).await.unwrap();
+        assert!(cache.get("d").await.unwrap().is_none());
+    }
+}
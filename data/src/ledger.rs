@@ -0,0 +1,297 @@
+use std::collections::{HashMap, VecDeque};
+
+use ecode::{Reason, Result, Status};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::model::HANDLING_FEE;
+
+/// Which open buy lots a sell consumes.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LotMethod {
+    #[default]
+    Fifo,
+    Lifo,
+    /// The lots listed in the sell's `buy_order_ids`, in that order. A repeated
+    /// id consumes its lot once.
+    SpecificLot,
+}
+
+/// Fee rates as a fraction of the quote amount, e.g. `0.001` for 0.1%.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeeSchedule {
+    pub maker: Decimal,
+    pub taker: Decimal,
+}
+
+impl Default for FeeSchedule {
+    fn default() -> Self {
+        FeeSchedule {
+            maker: HANDLING_FEE,
+            taker: HANDLING_FEE,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct FeeConf {
+    #[serde(default)]
+    pub default: FeeSchedule,
+    /// Per exchange overrides keyed by `cex_bot.exchange`.
+    #[serde(default)]
+    pub exchanges: HashMap<String, FeeSchedule>,
+}
+
+impl FeeConf {
+    pub fn schedule(&self, exchange: &str) -> &FeeSchedule {
+        self.exchanges.get(exchange).unwrap_or(&self.default)
+    }
+}
+
+/// A filled buy order still holding `qty` unsold. `fee` is the part of the
+/// buy fee attributed to the unsold quantity.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lot {
+    pub order_id: i64,
+    pub price: Decimal,
+    pub qty: Decimal,
+    pub fee: Decimal,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SellFill {
+    pub id: i64,
+    pub price: Decimal,
+    pub qty: Decimal,
+    pub fee: Decimal,
+    pub buy_order_ids: Vec<i64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LotMatch {
+    pub order_id: i64,
+    pub price: Decimal,
+    pub qty: Decimal,
+    pub fee: Decimal,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Realized {
+    pub sell_id: i64,
+    pub matches: Vec<LotMatch>,
+    pub proceeds: Decimal,
+    pub cost: Decimal,
+    /// Sell fee plus the matched share of the buy fees.
+    pub fee: Decimal,
+    pub profit: Decimal,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PnlSummary {
+    pub bot_id: i64,
+    pub sells: u64,
+    pub wins: u64,
+    pub losses: u64,
+    pub profit: Decimal,
+}
+
+impl PnlSummary {
+    pub fn add(&mut self, profit: Decimal) {
+        self.sells += 1;
+        if profit > Decimal::ZERO {
+            self.wins += 1;
+        } else if profit < Decimal::ZERO {
+            self.losses += 1;
+        }
+        self.profit += profit;
+    }
+}
+
+/// Open buy lots of one bot and symbol, consumed by sells in the order they
+/// were filled.
+#[derive(Debug, Clone)]
+pub struct Book {
+    method: LotMethod,
+    lots: VecDeque<Lot>,
+}
+
+impl Book {
+    /// `lots` must be in fill order. Lots without a positive quantity hold
+    /// nothing to sell and are dropped.
+    pub fn new(method: LotMethod, lots: Vec<Lot>) -> Self {
+        Book {
+            method,
+            lots: lots.into_iter().filter(|l| l.qty > Decimal::ZERO).collect(),
+        }
+    }
+
+    pub fn lots(&self) -> &VecDeque<Lot> {
+        &self.lots
+    }
+
+    /// Consumes lots for `sell`. On error the book is left untouched.
+    pub fn sell(&mut self, sell: &SellFill) -> Result<Realized> {
+        let mut lots = self.lots.clone();
+        let order: Vec<usize> = match self.method {
+            LotMethod::Fifo => (0..lots.len()).collect(),
+            LotMethod::Lifo => (0..lots.len()).rev().collect(),
+            LotMethod::SpecificLot => {
+                let mut order = vec![];
+                for id in sell.buy_order_ids.iter() {
+                    if let Some(i) = lots.iter().position(|l| l.order_id == *id) {
+                        if !order.contains(&i) {
+                            order.push(i);
+                        }
+                    }
+                }
+                order
+            }
+        };
+        let mut remaining = sell.qty;
+        let mut matches = vec![];
+        for i in order {
+            if remaining.is_zero() {
+                break;
+            }
+            let lot = &mut lots[i];
+            if lot.qty.is_zero() {
+                continue;
+            }
+            let qty = remaining.min(lot.qty);
+            let fee = lot.fee * qty / lot.qty;
+            matches.push(LotMatch {
+                order_id: lot.order_id,
+                price: lot.price,
+                qty,
+                fee,
+            });
+            lot.qty -= qty;
+            lot.fee -= fee;
+            remaining -= qty;
+        }
+        if !remaining.is_zero() {
+            return Err(Status::new(
+                409,
+                Reason::LedgerUnmatched,
+                format!("sell {} has {} left without open buy lots", sell.id, remaining),
+            ));
+        }
+        lots.retain(|l| !l.qty.is_zero());
+        self.lots = lots;
+
+        let proceeds = sell.price * sell.qty;
+        let cost: Decimal = matches.iter().map(|m| m.price * m.qty).sum();
+        let fee = sell.fee + matches.iter().map(|m| m.fee).sum::<Decimal>();
+        Ok(Realized {
+            sell_id: sell.id,
+            matches,
+            proceeds,
+            cost,
+            fee,
+            profit: proceeds - cost - fee,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn lots() -> Vec<Lot> {
+        vec![
+            Lot {
+                order_id: 1,
+                price: dec!(100),
+                qty: dec!(1),
+                fee: dec!(0.1),
+            },
+            Lot {
+                order_id: 2,
+                price: dec!(110),
+                qty: dec!(1),
+                fee: dec!(0.1),
+            },
+        ]
+    }
+
+    fn sell(qty: Decimal, buy_order_ids: Vec<i64>) -> SellFill {
+        SellFill {
+            id: 9,
+            price: dec!(120),
+            qty,
+            fee: dec!(0.2),
+            buy_order_ids,
+        }
+    }
+
+    #[test]
+    fn test_fifo() {
+        let mut book = Book::new(LotMethod::Fifo, lots());
+        let r = book.sell(&sell(dec!(1.5), vec![])).unwrap();
+        assert_eq!(r.cost, dec!(155));
+        assert_eq!(r.fee, dec!(0.35));
+        assert_eq!(r.profit, dec!(180) - dec!(155) - dec!(0.35));
+        assert_eq!(book.lots().len(), 1);
+        assert_eq!(book.lots()[0].qty, dec!(0.5));
+        assert_eq!(book.lots()[0].fee, dec!(0.05));
+    }
+
+    #[test]
+    fn test_lifo() {
+        let mut book = Book::new(LotMethod::Lifo, lots());
+        let r = book.sell(&sell(dec!(1), vec![])).unwrap();
+        assert_eq!(r.matches[0].order_id, 2);
+        assert_eq!(r.profit, dec!(120) - dec!(110) - dec!(0.3));
+    }
+
+    #[test]
+    fn test_specific_lot() {
+        let mut book = Book::new(LotMethod::SpecificLot, lots());
+        let r = book.sell(&sell(dec!(1), vec![2])).unwrap();
+        assert_eq!(r.matches.len(), 1);
+        assert_eq!(r.matches[0].order_id, 2);
+
+        let err = book.sell(&sell(dec!(1), vec![2])).unwrap_err();
+        assert_eq!(err.reason, Reason::LedgerUnmatched);
+    }
+
+    #[test]
+    fn test_specific_lot_repeated_id() {
+        let mut book = Book::new(LotMethod::SpecificLot, lots());
+        let err = book.sell(&sell(dec!(1.5), vec![2, 2])).unwrap_err();
+        assert_eq!(err.reason, Reason::LedgerUnmatched);
+        assert_eq!(book.lots(), &VecDeque::from(lots()));
+
+        let r = book.sell(&sell(dec!(1.5), vec![2, 2, 1])).unwrap();
+        assert_eq!(r.matches.len(), 2);
+        assert_eq!(r.cost, dec!(110) + dec!(50));
+    }
+
+    #[test]
+    fn test_zero_qty_lot() {
+        let mut lots = lots();
+        lots.insert(
+            0,
+            Lot {
+                order_id: 0,
+                price: dec!(90),
+                qty: dec!(0),
+                fee: dec!(0),
+            },
+        );
+        let mut book = Book::new(LotMethod::Fifo, lots);
+        assert_eq!(book.lots().len(), 2);
+        let r = book.sell(&sell(dec!(1), vec![])).unwrap();
+        assert_eq!(r.matches[0].order_id, 1);
+        assert_eq!(r.cost, dec!(100));
+    }
+
+    #[test]
+    fn test_oversell() {
+        let mut book = Book::new(LotMethod::Fifo, lots());
+        let err = book.sell(&sell(dec!(3), vec![])).unwrap_err();
+        assert_eq!(err.reason, Reason::LedgerUnmatched);
+        assert_eq!(book.lots(), &VecDeque::from(lots()));
+    }
+}
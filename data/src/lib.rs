@@ -1,13 +1,17 @@
 use std::{format, vec};
 use async_trait::async_trait;
-use chrono::{DateTime, Utc};
-use clickhouse_rs::Block;
-use clickhouse_rs::types::Complex;
+use chrono::{DateTime, TimeDelta, Utc};
 use serde::{Deserialize, Serialize};
 use ecode::Result;
 use crate::{
-    domain::{AggTrade, ForceOrderEvent, KlineEvent},
-    entity::{cex_market, cex_market_symbol},
+    codec::DomainEvent,
+    domain::{AggTrade, ClosePrice, ForceOrderEvent, KlineEvent},
+    entity::{
+        cex_binance_kline_csv, cex_bot, cex_bot_asset, cex_bot_order, cex_bot_position, cex_market, cex_market_symbol,
+    },
+    ledger::{FeeConf, LotMethod, PnlSummary, Realized},
+    model::{BotStatus, OrderDrift, OrderReport},
+    snapshot::{max_drawdown, AssetSnapshot, Drawdown, EquityPoint, PositionSnapshot},
 };
 
 pub mod entity;
@@ -17,6 +21,17 @@ pub mod model;
 pub mod query;
 pub mod cache;
 pub mod domain;
+pub mod schema;
+pub mod ledger;
+pub mod snapshot;
+pub mod codec;
+pub mod ingest;
+pub mod book;
+pub mod pubsub;
+pub mod bus;
+pub mod kline_cache;
+pub mod import;
+pub mod export;
 
 pub use validator::Validate;
 pub use redis::{
@@ -30,13 +45,50 @@ pub use rust_decimal_macros::dec;
 pub struct Database {
     pub driver: String,
     pub source: String,
+    /// Run pending `migration` steps when the connection is opened.
+    #[serde(default)]
+    pub auto_migrate: bool,
 }
 
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub struct Conf {
     pub database: Database,
+    #[serde(default)]
+    pub id: id::IdConf,
+    #[serde(default)]
+    pub csv: import::CsvConf,
 }
 
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct NewMarket {
+    pub exchange: String,
+    pub market: String,
+    pub key: String,
+    pub secret: String,
+    pub passphrase: String,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct NewMarketSymbol {
+    pub exchange: String,
+    pub market: String,
+    pub symbol: String,
+    pub interval: String,
+    pub base: String,
+    pub quote: String,
+}
+
+/// Optional filters for listing markets and symbols; `None` matches anything.
+/// `symbol` is ignored when listing markets.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct MarketFilter {
+    pub exchange: Option<String>,
+    pub market: Option<String>,
+    pub symbol: Option<String>,
+    pub status: Option<i32>,
+}
+
+/// Markets and symbols rows with `deleted_at` set are invisible to every method.
 #[async_trait]
 pub trait QueryMarketDao {
     async fn batch_get_market_by_status(&self) -> Result<Vec<cex_market::Model>>;
@@ -45,6 +97,170 @@ pub trait QueryMarketDao {
         exchange: &str,
         market: &str,
     ) -> ecode::Result<Vec<cex_market_symbol::Model>>;
+
+    /// Creates the market disabled, reviving a soft-deleted row with the same
+    /// (exchange, market). Fails with `Reason::MarketExists` for a live one.
+    async fn create_market(&self, market: NewMarket) -> Result<cex_market::Model>;
+    async fn update_market(&self, id: i64, market: NewMarket) -> Result<cex_market::Model>;
+    async fn delete_market(&self, id: i64) -> Result<()>;
+    async fn set_market_status(&self, id: i64, status: i32) -> Result<()>;
+    async fn get_market(&self, exchange: &str, market: &str) -> Result<Option<cex_market::Model>>;
+    async fn list_markets(&self, filter: &MarketFilter) -> Result<Vec<cex_market::Model>>;
+
+    /// Same revival and uniqueness rules as `create_market`, keyed by
+    /// (exchange, market, symbol).
+    async fn create_market_symbol(
+        &self,
+        symbol: NewMarketSymbol,
+    ) -> Result<cex_market_symbol::Model>;
+    async fn update_market_symbol(
+        &self,
+        id: i64,
+        symbol: NewMarketSymbol,
+    ) -> Result<cex_market_symbol::Model>;
+    async fn delete_market_symbol(&self, id: i64) -> Result<()>;
+    async fn set_market_symbol_status(&self, id: i64, status: i32) -> Result<()>;
+    async fn get_market_symbol(
+        &self,
+        exchange: &str,
+        market: &str,
+        symbol: &str,
+    ) -> Result<Option<cex_market_symbol::Model>>;
+    async fn list_market_symbols(
+        &self,
+        filter: &MarketFilter,
+    ) -> Result<Vec<cex_market_symbol::Model>>;
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct NewBot {
+    pub exchange: String,
+    pub market: String,
+    pub key: String,
+    pub secret: String,
+    pub passphrase: String,
+    pub tag: String,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct BotFilter {
+    pub exchange: Option<String>,
+    pub market: Option<String>,
+    pub tag: Option<String>,
+    pub status: Option<BotStatus>,
+}
+
+/// Bots are created in `BotStatus::Created`; `pause_bot`, `resume_bot` and
+/// `stop_bot` fail with `Reason::InvalidBotTransition` when the current status
+/// does not allow the move.
+#[async_trait]
+pub trait BotRepo {
+    async fn create_bot(&self, bot: NewBot) -> Result<cex_bot::Model>;
+    async fn get_bot(&self, id: i64) -> Result<cex_bot::Model>;
+    async fn list_bots(&self, filter: &BotFilter) -> Result<Vec<cex_bot::Model>>;
+    /// Moves a created or paused bot to `Running`.
+    async fn resume_bot(&self, id: i64) -> Result<cex_bot::Model>;
+    async fn pause_bot(&self, id: i64) -> Result<cex_bot::Model>;
+    async fn stop_bot(&self, id: i64) -> Result<cex_bot::Model>;
+    async fn rotate_bot_credentials(
+        &self,
+        id: i64,
+        key: &str,
+        secret: &str,
+        passphrase: &str,
+    ) -> Result<cex_bot::Model>;
+}
+
+#[async_trait]
+pub trait OrderRepo {
+    /// Stores an execution report keyed by (bot_id, order_id). Replaying a
+    /// report is a no-op, and a report older than the stored status or for
+    /// an order already closed is ignored. A soft-deleted order is revived.
+    async fn upsert_order(&self, report: &OrderReport) -> Result<cex_bot_order::Model>;
+    async fn get_order(&self, bot_id: i64, order_id: i64) -> Result<Option<cex_bot_order::Model>>;
+    async fn list_open_orders(&self, bot_id: i64) -> Result<Vec<cex_bot_order::Model>>;
+    /// Compares the stored open orders of `bot_id` with the exchange's open
+    /// orders. Nothing is written.
+    async fn reconcile_orders(&self, bot_id: i64, snapshot: &[OrderReport]) -> Result<OrderDrift>;
+}
+
+#[async_trait]
+pub trait LedgerRepo {
+    /// Replays every filled buy and sell of `bot_id` on `symbol` through a
+    /// `ledger::Book` and writes each sell's realized profit to
+    /// `cex_bot_sell_order.profit` in one transaction, so an unmatched sell
+    /// leaves every profit as it was. Recomputing is idempotent.
+    async fn settle_profit(
+        &self,
+        bot_id: i64,
+        symbol: &str,
+        method: LotMethod,
+        fees: &FeeConf,
+    ) -> Result<Vec<Realized>>;
+
+    /// Sums the stored profit of sells created in `[start, to)`.
+    async fn pnl_summary(
+        &self,
+        bot_id: i64,
+        start: &DateTime<Utc>,
+        to: &DateTime<Utc>,
+    ) -> Result<PnlSummary>;
+}
+
+/// Current account rows of a bot, as last written by the bot runner.
+#[async_trait]
+pub trait AccountRepo {
+    async fn list_bot_assets(&self, bot_id: i64) -> Result<Vec<cex_bot_asset::Model>>;
+    async fn list_bot_positions(&self, bot_id: i64) -> Result<Vec<cex_bot_position::Model>>;
+}
+
+/// One period of an archived `event` stream (`kline`, `aggTrade`, ...),
+/// `[start_time, end_time)` in ms. `interval` is empty for streams that have none.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImportPeriod {
+    /// Archive names carry neither, the importer fills them in from `CsvConf`.
+    pub exchange: String,
+    pub market: String,
+    pub event: String,
+    pub symbol: String,
+    pub interval: String,
+    pub start_time: i64,
+    pub end_time: i64,
+}
+
+/// Completed historical imports, kept in `cex_binance_kline_csv`.
+#[async_trait]
+pub trait ImportJobRepo {
+    /// Whether a recorded import already covers all of `period`.
+    async fn import_covered(&self, period: &ImportPeriod) -> Result<bool>;
+    async fn record_import(&self, period: &ImportPeriod) -> Result<cex_binance_kline_csv::Model>;
+}
+
+/// Time series of `AccountRepo` rows, see `snapshot::record_snapshots`.
+#[async_trait]
+pub trait SnapshotRepo {
+    async fn insert_asset_snapshots(&self, rows: &[AssetSnapshot]) -> Result<()>;
+    async fn insert_position_snapshots(&self, rows: &[PositionSnapshot]) -> Result<()>;
+
+    /// Equity of `asset` for `bot_id` in `[start, to)`, oldest first.
+    async fn fetch_equity_curve(
+        &self,
+        bot_id: i64,
+        asset: &str,
+        start: &DateTime<Utc>,
+        to: &DateTime<Utc>,
+    ) -> Result<Vec<EquityPoint>>;
+
+    async fn fetch_drawdown(
+        &self,
+        bot_id: i64,
+        asset: &str,
+        start: &DateTime<Utc>,
+        to: &DateTime<Utc>,
+    ) -> Result<Drawdown> {
+        let curve = self.fetch_equity_curve(bot_id, asset, start, to).await?;
+        Ok(max_drawdown(&curve))
+    }
 }
 
 #[async_trait]
@@ -55,7 +271,17 @@ pub trait AggTradeRepo {
         limit: u32,
     ) -> Result<Vec<AggTrade>>;
 
-    async fn fetch_all(&self, symbol: &str, interval: u32) -> Result<Block<Complex>>;
+    /// Closes of `symbol` in `[start, to)`, sampled every `interval`. The
+    /// interval must be a multiple of the symbol's stored kline interval.
+    async fn fetch_close_series(
+        &self,
+        symbol: &cex_market_symbol::Model,
+        interval: TimeDelta,
+        start: &DateTime<Utc>,
+        to: &DateTime<Utc>,
+    ) -> Result<Vec<ClosePrice>>;
+
+    async fn insert_agg_trades(&self, table: &str, trades: Vec<AggTrade>) -> Result<()>;
 }
 
 #[async_trait]
@@ -72,6 +298,15 @@ pub trait KlineRepo {
         start: &DateTime<Utc>,
         to: &DateTime<Utc>,
     ) -> Result<Vec<KlineEvent>>;
+
+    /// Klines opening in `[start, to)`, oldest first.
+    async fn fetch_kline_range(
+        &self,
+        table: &str,
+        start: &DateTime<Utc>,
+        to: &DateTime<Utc>,
+    ) -> Result<Vec<KlineEvent>>;
+
     async fn fetch_kline_time_limit_open(
         &self,
         table: &str,
@@ -99,6 +334,18 @@ pub trait KlineRepo {
     ) -> Result<Decimal>;
 
     async fn insert_kline(&self, table: &str, kline: KlineEvent) -> Result<()>;
+
+    /// Bulk variant of `insert_kline`, used by backfills.
+    async fn insert_klines(&self, table: &str, klines: Vec<KlineEvent>) -> Result<()> {
+        for kline in klines {
+            self.insert_kline(table, kline).await?;
+        }
+        Ok(())
+    }
+
+    /// Removes the klines opening in `[start, end)` (ms) and takes them out of
+    /// the resampled tables fed by `table`, so the period can be imported again.
+    async fn delete_klines(&self, table: &str, start: i64, end: i64) -> Result<()>;
 }
 
 #[async_trait]
@@ -109,6 +356,52 @@ pub trait ForceOrderRepo {
         side: &str,
         start: &DateTime<Utc>,
     ) -> Result<Decimal>;
+
+    async fn insert_force_orders(&self, table: &str, orders: Vec<ForceOrderEvent>) -> Result<()>;
+
+    /// Removes the orders with `trade_time` in `[start, end)` (ms).
+    async fn delete_force_orders(&self, table: &str, start: i64, end: i64) -> Result<()>;
+}
+
+/// Where events rejected by `ingest` are kept for inspection and replay.
+#[async_trait]
+pub trait DeadLetterRepo {
+    async fn dead_letter(&self, event: &DomainEvent, reason: &str) -> Result<()>;
+}
+
+/// One event read from an `EventBus` topic.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BusMessage {
+    /// Pass to `Subscription::ack` once handled.
+    pub id: String,
+    pub event: DomainEvent,
+}
+
+/// Topic based event transport, so the same strategy code can run on Redis
+/// in production and in process for tests and backtests.
+#[async_trait]
+pub trait EventBus {
+    /// Appends `event` to `topic` and returns its id.
+    async fn publish(&self, topic: &str, event: &DomainEvent) -> Result<String>;
+
+    /// Joins `group` on `topic` as `consumer`. Every group sees every event
+    /// published after it was first created; within a group each event goes
+    /// to one consumer.
+    async fn subscribe(
+        &self,
+        topic: &str,
+        group: &str,
+        consumer: &str,
+    ) -> Result<Box<dyn Subscription>>;
+}
+
+#[async_trait]
+pub trait Subscription: Send {
+    /// Waits for the next event, `None` once the bus is gone.
+    async fn next(&mut self) -> Result<Option<BusMessage>>;
+
+    /// Marks the event handled. Unacked events may be delivered again.
+    async fn ack(&mut self, id: &str) -> Result<()>;
 }
 
 
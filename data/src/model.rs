@@ -1,8 +1,61 @@
 use rust_decimal::Decimal;
 use rust_decimal_macros::dec;
+use serde::{Deserialize, Serialize};
+use sea_orm::{ActiveValue, ActiveValue::NotSet, JsonValue, Set};
+
+use crate::entity::{cex_bot_asset, cex_bot_order, cex_bot_position, cex_bot_sell_order};
 
 pub const HANDLING_FEE: Decimal = dec!(0.000);
 
+/// `status` column values of `cex_market` and `cex_market_symbol`.
+pub const STATUS_DISABLED: i32 = 0;
+pub const STATUS_ENABLED: i32 = 1;
+
+/// Lifecycle of a `cex_bot` row, stored in its `status` column.
+///
+/// `Created -> Running <-> Paused`, and any state may move to the terminal
+/// `Stopped`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BotStatus {
+    Created = 0,
+    Running = 1,
+    Paused = 2,
+    Stopped = 3,
+}
+
+impl BotStatus {
+    pub fn can_transition(self, to: BotStatus) -> bool {
+        use BotStatus::*;
+        matches!(
+            (self, to),
+            (Created, Running)
+                | (Running, Paused)
+                | (Paused, Running)
+                | (Created | Running | Paused, Stopped)
+        )
+    }
+}
+
+impl TryFrom<i32> for BotStatus {
+    type Error = i32;
+
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(BotStatus::Created),
+            1 => Ok(BotStatus::Running),
+            2 => Ok(BotStatus::Paused),
+            3 => Ok(BotStatus::Stopped),
+            v => Err(v),
+        }
+    }
+}
+
+impl From<BotStatus> for i32 {
+    fn from(value: BotStatus) -> Self {
+        value as i32
+    }
+}
+
 #[derive(Default, Debug, Clone)]
 pub struct AOrderStatusResponse {}
 
@@ -29,20 +82,241 @@ pub enum APositionSide {
     Short,
 }
 
+impl APositionSide {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            APositionSide::Both => "BOTH",
+            APositionSide::Long => "LONG",
+            APositionSide::Short => "SHORT",
+        }
+    }
+}
+
+impl std::str::FromStr for APositionSide {
+    type Err = ecode::Status;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "BOTH" => Ok(APositionSide::Both),
+            "LONG" => Ok(APositionSide::Long),
+            "SHORT" => Ok(APositionSide::Short),
+            _ => Err(ecode::Status::new(
+                400,
+                ecode::Reason::InvalidOrder,
+                format!("unknown position side {}", s),
+            )),
+        }
+    }
+}
+
+/// Also stored, as its discriminant, in `cex_bot_sell_order.status`.
 #[derive(Default, Debug, Clone, PartialEq)]
 pub enum AOrderStatus {
     #[default]
-    None,
-    XiaDan,
-    Filled,
-    Canceled,
-    Locked, // 针对买的订单
-    Selled, // 针对买的订单
+    None = 0,
+    XiaDan = 1,
+    Filled = 2,
+    Canceled = 3,
+    Locked = 4, // 针对买的订单
+    Selled = 5, // 针对买的订单
+}
+
+impl From<AOrderStatus> for i32 {
+    fn from(value: AOrderStatus) -> Self {
+        value as i32
+    }
+}
+
+/// Enums stored in `cex_bot_order` as the exchange's upper-case strings.
+macro_rules! str_enum {
+    ($name:ident { $($variant:ident => $s:literal),+ $(,)? }) => {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+        pub enum $name {
+            $($variant),+
+        }
+
+        impl $name {
+            pub fn as_str(&self) -> &'static str {
+                match self {
+                    $($name::$variant => $s),+
+                }
+            }
+        }
+
+        impl std::str::FromStr for $name {
+            type Err = ecode::Status;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                match s {
+                    $($s => Ok($name::$variant),)+
+                    _ => Err(ecode::Status::new(
+                        400,
+                        ecode::Reason::InvalidOrder,
+                        format!("unknown {} {}", stringify!($name), s),
+                    )),
+                }
+            }
+        }
+
+        impl std::fmt::Display for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.write_str(self.as_str())
+            }
+        }
+    };
+}
+
+str_enum!(OrderSide {
+    Buy => "BUY",
+    Sell => "SELL",
+});
+
+str_enum!(OrderType {
+    Limit => "LIMIT",
+    Market => "MARKET",
+    LimitMaker => "LIMIT_MAKER",
+    Stop => "STOP",
+    StopMarket => "STOP_MARKET",
+    StopLoss => "STOP_LOSS",
+    StopLossLimit => "STOP_LOSS_LIMIT",
+    TakeProfit => "TAKE_PROFIT",
+    TakeProfitLimit => "TAKE_PROFIT_LIMIT",
+    TakeProfitMarket => "TAKE_PROFIT_MARKET",
+    TrailingStopMarket => "TRAILING_STOP_MARKET",
+});
+
+str_enum!(TimeInForce {
+    Gtc => "GTC",
+    Ioc => "IOC",
+    Fok => "FOK",
+    Gtx => "GTX",
+});
+
+str_enum!(OrderStatus {
+    New => "NEW",
+    PartiallyFilled => "PARTIALLY_FILLED",
+    Filled => "FILLED",
+    Canceled => "CANCELED",
+    PendingCancel => "PENDING_CANCEL",
+    Rejected => "REJECTED",
+    Expired => "EXPIRED",
+});
+
+str_enum!(ExecutionType {
+    New => "NEW",
+    Canceled => "CANCELED",
+    Calculated => "CALCULATED",
+    Replaced => "REPLACED",
+    Rejected => "REJECTED",
+    Trade => "TRADE",
+    Expired => "EXPIRED",
+    Amendment => "AMENDMENT",
+});
+
+impl OrderStatus {
+    pub fn is_open(&self) -> bool {
+        matches!(
+            self,
+            OrderStatus::New | OrderStatus::PartiallyFilled | OrderStatus::PendingCancel
+        )
+    }
+
+    /// Orders only move forward: a late `NEW` report must not overwrite `FILLED`.
+    fn rank(&self) -> u8 {
+        match self {
+            OrderStatus::New => 0,
+            OrderStatus::PartiallyFilled => 1,
+            OrderStatus::PendingCancel => 2,
+            _ => 3,
+        }
+    }
+
+    /// Whether a stored order in this status may take `next`. A terminal
+    /// status is final, so a late `CANCELED` cannot overwrite `FILLED`.
+    pub fn can_become(&self, next: OrderStatus) -> bool {
+        self.is_open() && self.rank() <= next.rank()
+    }
+}
+
+/// A typed order as reported by the exchange, either from an execution report
+/// or an open-orders snapshot.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderReport {
+    pub bot_id: i64,
+    pub symbol: String,
+    pub order_id: i64,
+    pub side: OrderSide,
+    pub ty: OrderType,
+    pub time_in_force: TimeInForce,
+    pub original_qty: Decimal,
+    pub original_price: Decimal,
+    pub average_price: Decimal,
+    pub stop_price: Decimal,
+    pub execution_type: ExecutionType,
+    pub status: OrderStatus,
 }
 
-pub enum OrderSide {
-    Buy,
-    Sell,
+impl TryFrom<&cex_bot_order::Model> for OrderReport {
+    type Error = ecode::Status;
+
+    fn try_from(m: &cex_bot_order::Model) -> Result<Self, Self::Error> {
+        let table = "cex_bot_order";
+        Ok(OrderReport {
+            bot_id: m.bot_id,
+            symbol: m.symbol.clone(),
+            order_id: m.order_id,
+            side: m.side.parse()?,
+            ty: m.ty.parse()?,
+            time_in_force: m.time_in_force.parse()?,
+            original_qty: parse_decimal(table, m.id, "original_qty", &m.original_qty)?,
+            original_price: parse_decimal(table, m.id, "original_price", &m.original_price)?,
+            average_price: parse_decimal(table, m.id, "average_price", &m.average_price)?,
+            stop_price: parse_decimal(table, m.id, "stop_price", &m.stop_price)?,
+            execution_type: m.execution_type.parse()?,
+            status: m.status.parse()?,
+        })
+    }
+}
+
+/// Every column but the id and timestamps.
+impl From<&OrderReport> for cex_bot_order::ActiveModel {
+    fn from(o: &OrderReport) -> Self {
+        cex_bot_order::ActiveModel {
+            bot_id: Set(o.bot_id),
+            ty: Set(o.ty.to_string()),
+            side: Set(o.side.to_string()),
+            symbol: Set(o.symbol.clone()),
+            order_id: Set(o.order_id),
+            time_in_force: Set(o.time_in_force.to_string()),
+            original_qty: Set(o.original_qty.to_string()),
+            original_price: Set(o.original_price.to_string()),
+            average_price: Set(o.average_price.to_string()),
+            stop_price: Set(o.stop_price.to_string()),
+            execution_type: Set(o.execution_type.to_string()),
+            status: Set(o.status.to_string()),
+            ..Default::default()
+        }
+    }
+}
+
+/// Differences between stored open orders and an exchange snapshot.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct OrderDrift {
+    /// Open in the database but not open on the exchange.
+    pub missing_on_exchange: Vec<OrderReport>,
+    /// Open on the exchange but unknown or closed in the database.
+    pub unknown_locally: Vec<OrderReport>,
+    /// Open on both sides with different status, quantity or prices;
+    /// (stored, exchange).
+    pub mismatched: Vec<(OrderReport, OrderReport)>,
+}
+
+impl OrderDrift {
+    pub fn is_empty(&self) -> bool {
+        self.missing_on_exchange.is_empty()
+            && self.unknown_locally.is_empty()
+            && self.mismatched.is_empty()
+    }
 }
 
 #[derive(Default, Debug, Clone)]
@@ -64,3 +338,380 @@ pub struct ALimitOrder {
     pub price: Decimal,
     pub qty: Decimal,
 }
+
+/// Parses a numeric column stored as text, naming the row and column on failure.
+pub fn parse_decimal(table: &str, id: i64, column: &str, value: &str) -> ecode::Result<Decimal> {
+    value.trim().parse().map_err(|_| {
+        ecode::Status::new(
+            400,
+            ecode::Reason::InvalidDecimal,
+            format!("{} {}: {} is not a decimal: {:?}", table, id, column, value),
+        )
+    })
+}
+
+/// Like `parse_decimal`, with `NULL` and the empty string read as `None`.
+pub fn parse_opt_decimal(
+    table: &str,
+    id: i64,
+    column: &str,
+    value: Option<&str>,
+) -> ecode::Result<Option<Decimal>> {
+    match value.map(str::trim) {
+        None | Some("") => Ok(None),
+        Some(v) => parse_decimal(table, id, column, v).map(Some),
+    }
+}
+
+/// Sets `id` only for rows that already exist (`id != 0`).
+fn set_id(id: i64) -> ActiveValue<i64> {
+    if id == 0 {
+        NotSet
+    } else {
+        Set(id)
+    }
+}
+
+/// Typed `cex_bot_asset` row.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct BotAsset {
+    pub id: i64,
+    pub bot_id: i64,
+    pub asset: String,
+    pub initial_margin: Decimal,
+    pub maint_margin: Decimal,
+    pub margin_balance: Decimal,
+    pub open_order_initial_margin: Decimal,
+    pub position_initial_margin: Decimal,
+    pub unrealized_profit: Decimal,
+    pub wallet_balance: Decimal,
+    pub cross_wallet_balance: Decimal,
+    pub cross_un_pnl: Decimal,
+    pub available_balance: Decimal,
+    pub margin_available: Option<bool>,
+}
+
+impl TryFrom<&cex_bot_asset::Model> for BotAsset {
+    type Error = ecode::Status;
+
+    fn try_from(m: &cex_bot_asset::Model) -> Result<Self, Self::Error> {
+        let t = "cex_bot_asset";
+        Ok(BotAsset {
+            id: m.id,
+            bot_id: m.bot_id,
+            asset: m.asset.clone(),
+            initial_margin: parse_decimal(t, m.id, "initial_margin", &m.initial_margin)?,
+            maint_margin: parse_decimal(t, m.id, "maint_margin", &m.maint_margin)?,
+            margin_balance: parse_decimal(t, m.id, "margin_balance", &m.margin_balance)?,
+            open_order_initial_margin: parse_decimal(
+                t,
+                m.id,
+                "open_order_initial_margin",
+                &m.open_order_initial_margin,
+            )?,
+            position_initial_margin: parse_decimal(
+                t,
+                m.id,
+                "position_initial_margin",
+                &m.position_initial_margin,
+            )?,
+            unrealized_profit: parse_decimal(t, m.id, "unrealized_profit", &m.unrealized_profit)?,
+            wallet_balance: parse_decimal(t, m.id, "wallet_balance", &m.wallet_balance)?,
+            cross_wallet_balance: parse_decimal(
+                t,
+                m.id,
+                "cross_wallet_balance",
+                &m.cross_wallet_balance,
+            )?,
+            cross_un_pnl: parse_decimal(t, m.id, "cross_un_pnl", &m.cross_un_pnl)?,
+            available_balance: parse_decimal(t, m.id, "available_balance", &m.available_balance)?,
+            margin_available: m.margin_available.map(|v| v != 0),
+        })
+    }
+}
+
+/// Every column but the timestamps; `id` is left unset for new rows.
+impl From<&BotAsset> for cex_bot_asset::ActiveModel {
+    fn from(a: &BotAsset) -> Self {
+        cex_bot_asset::ActiveModel {
+            id: set_id(a.id),
+            bot_id: Set(a.bot_id),
+            asset: Set(a.asset.clone()),
+            initial_margin: Set(a.initial_margin.to_string()),
+            maint_margin: Set(a.maint_margin.to_string()),
+            margin_balance: Set(a.margin_balance.to_string()),
+            open_order_initial_margin: Set(a.open_order_initial_margin.to_string()),
+            position_initial_margin: Set(a.position_initial_margin.to_string()),
+            unrealized_profit: Set(a.unrealized_profit.to_string()),
+            wallet_balance: Set(a.wallet_balance.to_string()),
+            cross_wallet_balance: Set(a.cross_wallet_balance.to_string()),
+            cross_un_pnl: Set(a.cross_un_pnl.to_string()),
+            available_balance: Set(a.available_balance.to_string()),
+            margin_available: Set(a.margin_available.map(i16::from)),
+            ..Default::default()
+        }
+    }
+}
+
+/// Typed `cex_bot_position` row.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BotPosition {
+    pub id: i64,
+    pub bot_id: i64,
+    pub isolated: bool,
+    pub leverage: Decimal,
+    pub initial_margin: Decimal,
+    pub maint_margin: Decimal,
+    pub open_order_initial_margin: Decimal,
+    pub position_initial_margin: Decimal,
+    pub symbol: String,
+    pub unrealized_profit: Decimal,
+    pub entry_price: Decimal,
+    pub max_notional: Decimal,
+    pub side: APositionSide,
+    pub amount: Decimal,
+    pub notional: Decimal,
+    pub bid_notional: Decimal,
+    pub ask_notional: Decimal,
+}
+
+impl TryFrom<&cex_bot_position::Model> for BotPosition {
+    type Error = ecode::Status;
+
+    fn try_from(m: &cex_bot_position::Model) -> Result<Self, Self::Error> {
+        let t = "cex_bot_position";
+        Ok(BotPosition {
+            id: m.id,
+            bot_id: m.bot_id,
+            isolated: m.isolated != 0,
+            leverage: parse_decimal(t, m.id, "leverage", &m.leverage)?,
+            initial_margin: parse_decimal(t, m.id, "initial_margin", &m.initial_margin)?,
+            maint_margin: parse_decimal(t, m.id, "maint_margin", &m.maint_margin)?,
+            open_order_initial_margin: parse_decimal(
+                t,
+                m.id,
+                "open_order_initial_margin",
+                &m.open_order_initial_margin,
+            )?,
+            position_initial_margin: parse_decimal(
+                t,
+                m.id,
+                "position_initial_margin",
+                &m.position_initial_margin,
+            )?,
+            symbol: m.symbol.clone(),
+            unrealized_profit: parse_decimal(t, m.id, "unrealized_profit", &m.unrealized_profit)?,
+            entry_price: parse_decimal(t, m.id, "entry_price", &m.entry_price)?,
+            max_notional: parse_decimal(t, m.id, "max_notional", &m.max_notional)?,
+            side: m.side.parse()?,
+            amount: parse_decimal(t, m.id, "amount", &m.amount)?,
+            notional: parse_decimal(t, m.id, "notional", &m.notional)?,
+            bid_notional: parse_decimal(t, m.id, "bid_notional", &m.bid_notional)?,
+            ask_notional: parse_decimal(t, m.id, "ask_notional", &m.ask_notional)?,
+        })
+    }
+}
+
+/// Every column but the timestamps; `id` is left unset for new rows.
+impl From<&BotPosition> for cex_bot_position::ActiveModel {
+    fn from(p: &BotPosition) -> Self {
+        cex_bot_position::ActiveModel {
+            id: set_id(p.id),
+            bot_id: Set(p.bot_id),
+            isolated: Set(i16::from(p.isolated)),
+            leverage: Set(p.leverage.to_string()),
+            initial_margin: Set(p.initial_margin.to_string()),
+            maint_margin: Set(p.maint_margin.to_string()),
+            open_order_initial_margin: Set(p.open_order_initial_margin.to_string()),
+            position_initial_margin: Set(p.position_initial_margin.to_string()),
+            symbol: Set(p.symbol.clone()),
+            unrealized_profit: Set(p.unrealized_profit.to_string()),
+            entry_price: Set(p.entry_price.to_string()),
+            max_notional: Set(p.max_notional.to_string()),
+            side: Set(p.side.as_str().to_string()),
+            amount: Set(p.amount.to_string()),
+            notional: Set(p.notional.to_string()),
+            bid_notional: Set(p.bid_notional.to_string()),
+            ask_notional: Set(p.ask_notional.to_string()),
+            ..Default::default()
+        }
+    }
+}
+
+/// Typed `cex_bot_sell_order` row.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct BotSellOrder {
+    pub id: i64,
+    pub bot_id: i64,
+    pub ty: i32,
+    pub symbol: String,
+    pub client_order_id: String,
+    pub order_id: i64,
+    pub status: i32,
+    pub price: Decimal,
+    pub quantity: Decimal,
+    pub real_price: Option<Decimal>,
+    pub real_quantity: Option<Decimal>,
+    pub real_quote_qty: Option<Decimal>,
+    pub quote_fee: Option<Decimal>,
+    pub cancel_order_id: Option<i64>,
+    pub cancel_order_status: Option<i32>,
+    pub buy_order_ids: Vec<i64>,
+    pub profit: Option<Decimal>,
+}
+
+impl TryFrom<&cex_bot_sell_order::Model> for BotSellOrder {
+    type Error = ecode::Status;
+
+    fn try_from(m: &cex_bot_sell_order::Model) -> Result<Self, Self::Error> {
+        let t = "cex_bot_sell_order";
+        let buy_order_ids = match &m.buy_order_ids {
+            JsonValue::Null => vec![],
+            JsonValue::Array(ids) => ids
+                .iter()
+                .map(|id| id.as_i64().or_else(|| id.as_str()?.parse().ok()))
+                .collect::<Option<Vec<i64>>>()
+                .ok_or_else(|| {
+                    ecode::Status::new(
+                        400,
+                        ecode::Reason::InvalidOrderIds,
+                        format!("{} {}: buy_order_ids is not a list of ids", t, m.id),
+                    )
+                })?,
+            v => {
+                return Err(ecode::Status::new(
+                    400,
+                    ecode::Reason::InvalidOrderIds,
+                    format!("{} {}: buy_order_ids is not a list: {}", t, m.id, v),
+                ))
+            }
+        };
+        Ok(BotSellOrder {
+            id: m.id,
+            bot_id: m.bot_id,
+            ty: m.ty,
+            symbol: m.symbol.clone(),
+            client_order_id: m.client_order_id.clone(),
+            order_id: m.order_id,
+            status: m.status,
+            price: parse_decimal(t, m.id, "price", &m.price)?,
+            quantity: parse_decimal(t, m.id, "quantity", &m.quantity)?,
+            real_price: parse_opt_decimal(t, m.id, "real_price", m.real_price.as_deref())?,
+            real_quantity: parse_opt_decimal(t, m.id, "real_quantity", m.real_quantity.as_deref())?,
+            real_quote_qty: parse_opt_decimal(
+                t,
+                m.id,
+                "real_quote_qty",
+                m.real_quote_qty.as_deref(),
+            )?,
+            quote_fee: parse_opt_decimal(t, m.id, "quote_fee", m.quote_fee.as_deref())?,
+            cancel_order_id: m.cancel_order_id,
+            cancel_order_status: m.cancel_order_status,
+            buy_order_ids,
+            profit: parse_opt_decimal(t, m.id, "profit", m.profit.as_deref())?,
+        })
+    }
+}
+
+/// Every column but the timestamps. The id comes from `IDGen` and is always set.
+impl From<&BotSellOrder> for cex_bot_sell_order::ActiveModel {
+    fn from(o: &BotSellOrder) -> Self {
+        cex_bot_sell_order::ActiveModel {
+            id: Set(o.id),
+            bot_id: Set(o.bot_id),
+            ty: Set(o.ty),
+            symbol: Set(o.symbol.clone()),
+            client_order_id: Set(o.client_order_id.clone()),
+            order_id: Set(o.order_id),
+            status: Set(o.status),
+            price: Set(o.price.to_string()),
+            quantity: Set(o.quantity.to_string()),
+            real_price: Set(o.real_price.map(|v| v.to_string())),
+            real_quantity: Set(o.real_quantity.map(|v| v.to_string())),
+            real_quote_qty: Set(o.real_quote_qty.map(|v| v.to_string())),
+            quote_fee: Set(o.quote_fee.map(|v| v.to_string())),
+            cancel_order_id: Set(o.cancel_order_id),
+            cancel_order_status: Set(o.cancel_order_status),
+            buy_order_ids: Set(JsonValue::from(o.buy_order_ids.clone())),
+            profit: Set(o.profit.map(|v| v.to_string())),
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_decimal() {
+        assert_eq!(parse_decimal("t", 1, "c", " 1.50 ").unwrap(), dec!(1.50));
+        let err = parse_decimal("cex_bot_asset", 7, "wallet_balance", "abc").unwrap_err();
+        assert_eq!(err.reason, ecode::Reason::InvalidDecimal);
+        assert!(err.message.contains("cex_bot_asset 7: wallet_balance"));
+        assert_eq!(parse_opt_decimal("t", 1, "c", Some("")).unwrap(), None);
+        assert_eq!(parse_opt_decimal("t", 1, "c", None).unwrap(), None);
+    }
+
+    #[test]
+    fn test_order_status_can_become() {
+        use OrderStatus::*;
+        assert!(New.can_become(New));
+        assert!(New.can_become(Filled));
+        assert!(PartiallyFilled.can_become(Canceled));
+        assert!(!PartiallyFilled.can_become(New));
+        for done in [Filled, Canceled, Rejected, Expired] {
+            for next in [New, Filled, Canceled, Rejected, Expired] {
+                assert!(!done.can_become(next), "{} -> {}", done, next);
+            }
+        }
+    }
+
+    #[test]
+    fn test_sell_order_round_trip() {
+        let order = BotSellOrder {
+            id: 42,
+            bot_id: 1,
+            symbol: "ETHUSDT".to_string(),
+            order_id: 7,
+            price: dec!(2000.5),
+            quantity: dec!(0.01),
+            real_price: Some(dec!(2001)),
+            buy_order_ids: vec![3, 4],
+            ..Default::default()
+        };
+        let am: cex_bot_sell_order::ActiveModel = (&order).into();
+        let m = cex_bot_sell_order::Model {
+            id: 42,
+            bot_id: 1,
+            ty: 0,
+            symbol: "ETHUSDT".to_string(),
+            client_order_id: String::new(),
+            order_id: 7,
+            status: 0,
+            price: am.price.unwrap(),
+            quantity: am.quantity.unwrap(),
+            real_price: am.real_price.unwrap(),
+            real_quantity: None,
+            real_quote_qty: None,
+            quote_fee: None,
+            cancel_order_id: None,
+            cancel_order_status: None,
+            buy_order_ids: am.buy_order_ids.unwrap(),
+            profit: None,
+            created_at: None,
+            updated_at: None,
+            deleted_at: None,
+        };
+        assert_eq!(BotSellOrder::try_from(&m).unwrap(), order);
+
+        for ids in [serde_json::json!(["3", "x"]), serde_json::json!({"id": 3})] {
+            let m = cex_bot_sell_order::Model {
+                buy_order_ids: ids,
+                ..m.clone()
+            };
+            let err = BotSellOrder::try_from(&m).unwrap_err();
+            assert_eq!(err.reason, ecode::Reason::InvalidOrderIds);
+        }
+    }
+}
@@ -0,0 +1,178 @@
+//! Typed Redis pub/sub for domain events. Every event is published as
+//! versioned JSON on `event:{kind}:{exchange}:{market}:{symbol}`, so
+//! subscribers can pick streams by glob pattern.
+
+use std::{pin::Pin, time::Duration};
+
+use ecode::Result;
+use futures_util::{Stream, StreamExt};
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use crate::{cache::RedisQuery, codec::DomainEvent};
+
+const CHANNEL_PREFIX: &str = "event";
+const RECONNECT_MIN: Duration = Duration::from_millis(100);
+const RECONNECT_MAX: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Topic {
+    pub exchange: String,
+    pub market: String,
+    pub symbol: String,
+}
+
+impl Topic {
+    pub fn new(exchange: &str, market: &str, symbol: &str) -> Self {
+        Topic {
+            exchange: exchange.to_string(),
+            market: market.to_string(),
+            symbol: symbol.to_string(),
+        }
+    }
+
+    pub fn channel(&self, kind: &str) -> String {
+        format!(
+            "{}:{}:{}:{}:{}",
+            CHANNEL_PREFIX, kind, self.exchange, self.market, self.symbol
+        )
+    }
+
+    /// Inverse of `channel`, returning the kind and the topic.
+    pub fn parse_channel(channel: &str) -> Option<(String, Topic)> {
+        let mut parts = channel.splitn(5, ':');
+        if parts.next()? != CHANNEL_PREFIX {
+            return None;
+        }
+        let kind = parts.next()?.to_string();
+        let topic = Topic::new(parts.next()?, parts.next()?, parts.next()?);
+        Some((kind, topic))
+    }
+}
+
+/// Selects channels; `None` matches anything.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TopicFilter {
+    pub kind: Option<String>,
+    pub exchange: Option<String>,
+    pub market: Option<String>,
+    pub symbol: Option<String>,
+}
+
+impl TopicFilter {
+    pub fn pattern(&self) -> String {
+        let part = |v: &Option<String>| v.clone().unwrap_or_else(|| "*".to_string());
+        format!(
+            "{}:{}:{}:{}:{}",
+            CHANNEL_PREFIX,
+            part(&self.kind),
+            part(&self.exchange),
+            part(&self.market),
+            part(&self.symbol)
+        )
+    }
+}
+
+/// An event received on `topic`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Delivery {
+    pub topic: Topic,
+    pub event: DomainEvent,
+}
+
+pub type DeliveryStream = Pin<Box<dyn Stream<Item = Delivery> + Send>>;
+
+impl RedisQuery {
+    /// Publishes `event` on its channel. Returns the number of subscribers
+    /// that got it.
+    pub async fn publish_event(&self, topic: &Topic, event: &DomainEvent) -> Result<usize> {
+        let mut conn = self.db.get_async_connection().await?;
+        Ok(conn.publish(topic.channel(event.kind()), event.to_json()?).await?)
+    }
+
+    /// Subscribes to every channel matched by `filters`. The connection is
+    /// re-established and the patterns resubscribed whenever it drops; events
+    /// published meanwhile are lost, as with any Redis pub/sub. Messages that
+    /// do not decode are logged and skipped. Dropping the stream unsubscribes.
+    pub fn subscribe_events(&self, filters: Vec<TopicFilter>) -> DeliveryStream {
+        let (tx, mut rx) = mpsc::channel(1024);
+        let client = self.db.clone();
+        let patterns: Vec<String> = filters.iter().map(TopicFilter::pattern).collect();
+        tokio::spawn(async move {
+            let mut backoff = RECONNECT_MIN;
+            while !tx.is_closed() {
+                match subscribe_once(&client, &patterns, &tx).await {
+                    Ok(()) => backoff = RECONNECT_MIN,
+                    Err(e) => log::warn!("pubsub {:?}: {}", patterns, e),
+                }
+                if tx.is_closed() {
+                    break;
+                }
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(RECONNECT_MAX);
+            }
+        });
+        Box::pin(futures_util::stream::poll_fn(move |cx| rx.poll_recv(cx)))
+    }
+}
+
+/// Forwards messages until the connection drops (`Ok`) or cannot be set up.
+async fn subscribe_once(
+    client: &redis::Client,
+    patterns: &[String],
+    tx: &mpsc::Sender<Delivery>,
+) -> Result<()> {
+    let mut pubsub = client.get_async_connection().await?.into_pubsub();
+    for pattern in patterns {
+        pubsub.psubscribe(pattern).await?;
+    }
+    let mut messages = pubsub.on_message();
+    loop {
+        let msg = tokio::select! {
+            _ = tx.closed() => return Ok(()),
+            msg = messages.next() => match msg {
+                Some(msg) => msg,
+                None => return Ok(()),
+            },
+        };
+        let channel = msg.get_channel_name().to_string();
+        let decoded = msg
+            .get_payload::<String>()
+            .map_err(Into::into)
+            .and_then(|payload| DomainEvent::from_json(&payload));
+        match (Topic::parse_channel(&channel), decoded) {
+            (Some((_, topic)), Ok(event)) => {
+                if tx.send(Delivery { topic, event }).await.is_err() {
+                    return Ok(());
+                }
+            }
+            (_, Err(e)) => log::warn!("pubsub {}: {}", channel, e),
+            (None, _) => log::warn!("pubsub {}: not an event channel", channel),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_channel() {
+        let topic = Topic::new("binance", "futures", "ETHUSDT");
+        let channel = topic.channel("kline");
+        assert_eq!(channel, "event:kline:binance:futures:ETHUSDT");
+        assert_eq!(
+            Topic::parse_channel(&channel),
+            Some(("kline".to_string(), topic))
+        );
+        assert_eq!(Topic::parse_channel("other:kline"), None);
+
+        let filter = TopicFilter {
+            exchange: Some("binance".to_string()),
+            symbol: Some("ETHUSDT".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(filter.pattern(), "event:*:binance:*:ETHUSDT");
+    }
+}
@@ -0,0 +1,452 @@
+use chrono::Utc;
+use clickhouse_rs::{row, Block};
+use serde::{Deserialize, Serialize};
+
+use ecode::{Reason, Result, Status};
+
+use crate::{
+    clickhouse::{aggtrade_table, kline_table, liquidation_table, ClickhouseQuery},
+    domain::interval_duration,
+    entity::cex_market_symbol,
+};
+
+const MIGRATION_TABLE: &str = "schema_migrations";
+
+/// Workspace wide ClickHouse migrations, applied in `version` order. Per-symbol
+/// tables are not listed here, they are created on demand by `ensure_tables`.
+/// Versions are never reused; version 1 created the shared `kline_1m`, which
+/// the per-symbol tables replaced.
+const MIGRATIONS: &[(u32, &str, &str)] = &[
+    (
+        2,
+        "create bot_asset_snapshot",
+        "CREATE TABLE IF NOT EXISTS bot_asset_snapshot
+(
+    time Int64,
+    bot_id Int64,
+    asset LowCardinality(String),
+    wallet_balance Decimal(18, 8),
+    unrealized_profit Decimal(18, 8),
+    margin_balance Decimal(18, 8),
+    available_balance Decimal(18, 8),
+    initial_margin Decimal(18, 8),
+    maint_margin Decimal(18, 8)
+)
+ENGINE = ReplacingMergeTree
+PARTITION BY toYYYYMM(toDateTime(intDiv(time, 1000)))
+ORDER BY (bot_id, asset, time)",
+    ),
+    (
+        3,
+        "create bot_position_snapshot",
+        "CREATE TABLE IF NOT EXISTS bot_position_snapshot
+(
+    time Int64,
+    bot_id Int64,
+    symbol LowCardinality(String),
+    side LowCardinality(String),
+    isolated UInt8,
+    leverage Decimal(18, 8),
+    amount Decimal(18, 8),
+    entry_price Decimal(18, 8),
+    unrealized_profit Decimal(18, 8),
+    notional Decimal(18, 8),
+    initial_margin Decimal(18, 8),
+    maint_margin Decimal(18, 8)
+)
+ENGINE = ReplacingMergeTree
+PARTITION BY toYYYYMM(toDateTime(intDiv(time, 1000)))
+ORDER BY (bot_id, symbol, side, time)",
+    ),
+    (
+        4,
+        "drop kline_1m",
+        "DROP TABLE IF EXISTS kline_1m",
+    ),
+];
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct SchemaConf {
+    /// Days to keep klines, forever when unset.
+    pub kline_ttl_days: Option<u32>,
+    pub aggtrade_ttl_days: Option<u32>,
+    pub liquidation_ttl_days: Option<u32>,
+    /// Coarser intervals (e.g. `1h`, `1d`) maintained by materialized views
+    /// over the symbol's base kline table. Views only see klines inserted
+    /// after they are created; older history is not backfilled.
+    pub resample: Vec<String>,
+}
+
+fn ttl(column: &str, days: Option<u32>) -> String {
+    match days {
+        Some(days) => format!(
+            "\nTTL toDateTime(intDiv({}, 1000)) + INTERVAL {} DAY",
+            column, days
+        ),
+        None => String::new(),
+    }
+}
+
+pub fn kline_ddl(table: &str, ttl_days: Option<u32>) -> String {
+    format!(
+        "CREATE TABLE IF NOT EXISTS {}
+(
+    id Int64,
+    event String,
+    symbol LowCardinality(String),
+    start_time Int64,
+    end_time Int64,
+    interval LowCardinality(String),
+    first_trade_id Int64,
+    last_trade_id Int64,
+    open Decimal(18, 8),
+    close Decimal(18, 8),
+    high Decimal(18, 8),
+    low Decimal(18, 8),
+    volume Decimal(18, 8),
+    trade_num Int64,
+    quote_volume Decimal(18, 8),
+    active_buy_volume Decimal(18, 8),
+    active_buy_quote_volume Decimal(18, 8),
+    ema7 Decimal(18, 8),
+    ema25 Decimal(18, 8),
+    macd Decimal(18, 8),
+    rsi Decimal(18, 8)
+)
+ENGINE = ReplacingMergeTree
+PARTITION BY toYYYYMM(toDateTime(intDiv(id, 1000)))
+ORDER BY id{}",
+        table,
+        ttl("id", ttl_days)
+    )
+}
+
+pub fn aggtrade_ddl(table: &str, ttl_days: Option<u32>) -> String {
+    format!(
+        "CREATE TABLE IF NOT EXISTS {}
+(
+    id Int64,
+    time Int64,
+    event String,
+    symbol LowCardinality(String),
+    agg_trade_id Int64,
+    price Decimal(18, 8),
+    quantity Decimal(18, 8),
+    first_trade_id Int64,
+    last_trade_id Int64,
+    trade_time Int64,
+    maker Bool
+)
+ENGINE = ReplacingMergeTree
+PARTITION BY toYYYYMM(toDateTime(intDiv(trade_time, 1000)))
+ORDER BY id{}",
+        table,
+        ttl("trade_time", ttl_days)
+    )
+}
+
+pub fn liquidation_ddl(table: &str, ttl_days: Option<u32>) -> String {
+    format!(
+        "CREATE TABLE IF NOT EXISTS {}
+(
+    id Int64,
+    exchange LowCardinality(String),
+    market LowCardinality(String),
+    event String,
+    symbol LowCardinality(String),
+    side LowCardinality(String),
+    order_type LowCardinality(String),
+    time_in_force LowCardinality(String),
+    orig_quantity Decimal(18, 8),
+    price Decimal(18, 8),
+    avg_price Decimal(18, 8),
+    avg_value Decimal(18, 8),
+    order_status LowCardinality(String),
+    last_filled_qty Decimal(18, 8),
+    accumulated_filled_qty Decimal(18, 8),
+    trade_time Int64
+)
+ENGINE = MergeTree
+PARTITION BY toYYYYMM(toDateTime(intDiv(trade_time, 1000)))
+ORDER BY (side, id){}",
+        table,
+        ttl("trade_time", ttl_days)
+    )
+}
+
+/// Resampled klines live apart from the `kline_table` of the same interval,
+/// which the `KlineRepo` fetches read as plain rows.
+pub fn resample_table_name(symbol: &str, interval: &str) -> String {
+    format!("kline_{}_{}_agg", symbol, interval).to_ascii_lowercase()
+}
+
+/// Bucket width of a resample `interval` over a `base` interval kline table.
+/// It must be a coarser multiple of the base, e.g. `1h` over `1m`.
+fn resample_step(base: &str, interval: &str) -> Result<i64> {
+    let unknown = |interval: &str| {
+        Status::new(
+            400,
+            Reason::InvalidInterval,
+            format!("unknown kline interval {}", interval),
+        )
+    };
+    let base_ms = interval_duration(base)
+        .ok_or_else(|| unknown(base))?
+        .num_milliseconds();
+    let step = interval_duration(interval)
+        .ok_or_else(|| unknown(interval))?
+        .num_milliseconds();
+    if step <= base_ms || step % base_ms != 0 {
+        return Err(Status::new(
+            400,
+            Reason::InvalidInterval,
+            format!(
+                "resample interval {} is not a coarser multiple of {}",
+                interval, base
+            ),
+        ));
+    }
+    Ok(step)
+}
+
+/// The aggregation behind a resample view. `filter` is spliced in before the
+/// `GROUP BY`, e.g. to rebuild a range of buckets.
+fn resample_select(source: &str, interval: &str, step_ms: i64, filter: &str) -> String {
+    format!(
+        "SELECT
+    {}.id - {}.id % {} AS id,
+    symbol,
+    '{}' AS interval,
+    min(first_trade_id) AS first_trade_id,
+    max(last_trade_id) AS last_trade_id,
+    argMinState(open, {}.id) AS open,
+    argMaxState(close, {}.id) AS close,
+    max(high) AS high,
+    min(low) AS low,
+    sum(toDecimal128(volume, 8)) AS volume,
+    sum(trade_num) AS trade_num,
+    sum(toDecimal128(quote_volume, 8)) AS quote_volume
+FROM {}{}
+GROUP BY id, symbol",
+        source, source, step_ms, interval, source, source, source, filter
+    )
+}
+
+/// Materialized view rolling `source` klines up to `step_ms` buckets. The
+/// target keeps aggregate states, so readers must `GROUP BY id` and use
+/// `argMinMerge(open)` / `argMaxMerge(close)`. The view is created without
+/// `POPULATE`, so rows already in `source` are not aggregated.
+pub fn resample_ddl(source: &str, target: &str, interval: &str, step_ms: i64) -> [String; 2] {
+    [
+        format!(
+            "CREATE TABLE IF NOT EXISTS {}
+(
+    id Int64,
+    symbol LowCardinality(String),
+    interval LowCardinality(String),
+    first_trade_id SimpleAggregateFunction(min, Int64),
+    last_trade_id SimpleAggregateFunction(max, Int64),
+    open AggregateFunction(argMin, Decimal(18, 8), Int64),
+    close AggregateFunction(argMax, Decimal(18, 8), Int64),
+    high SimpleAggregateFunction(max, Decimal(18, 8)),
+    low SimpleAggregateFunction(min, Decimal(18, 8)),
+    volume SimpleAggregateFunction(sum, Decimal(38, 8)),
+    trade_num SimpleAggregateFunction(sum, Int64),
+    quote_volume SimpleAggregateFunction(sum, Decimal(38, 8))
+)
+ENGINE = AggregatingMergeTree
+PARTITION BY toYYYYMM(toDateTime(intDiv(id, 1000)))
+ORDER BY (symbol, id)",
+            target
+        ),
+        format!(
+            "CREATE MATERIALIZED VIEW IF NOT EXISTS {}_mv TO {} AS\n{}",
+            target,
+            target,
+            resample_select(source, interval, step_ms, "")
+        ),
+    ]
+}
+
+/// Target table and interval of a view created by `resample_ddl`.
+fn resample_view_target(view: &str) -> Option<(&str, &str)> {
+    let target = view.strip_suffix("_mv")?;
+    let interval = target.strip_suffix("_agg")?.rsplit('_').next()?;
+    Some((target, interval))
+}
+
+/// The `step_ms` buckets overlapping `[start, end)`.
+fn bucket_range(start: i64, end: i64, step_ms: i64) -> (i64, i64) {
+    let lo = start - start.rem_euclid(step_ms);
+    let hi = end + (step_ms - end.rem_euclid(step_ms)) % step_ms;
+    (lo, hi)
+}
+
+impl ClickhouseQuery {
+    /// Applies every pending entry of `MIGRATIONS` and records it in the
+    /// version table. Safe to run on every start.
+    pub async fn migrate(&self) -> Result<u32> {
+        let mut client = self.db.get_handle().await?;
+        client
+            .execute(format!(
+                "CREATE TABLE IF NOT EXISTS {}
+(
+    version UInt32,
+    name String,
+    applied_at Int64
+)
+ENGINE = MergeTree
+ORDER BY version",
+                MIGRATION_TABLE
+            ))
+            .await?;
+        let block = client
+            .query(format!(
+                "SELECT toUInt32(max(version)) AS version FROM {}",
+                MIGRATION_TABLE
+            ))
+            .fetch_all()
+            .await?;
+        let applied: u32 = block.get(0, "version")?;
+        let mut current = applied;
+        for (version, name, sql) in MIGRATIONS.iter().filter(|m| m.0 > applied) {
+            log::info!("clickhouse migrate {} {}", version, name);
+            client.execute(*sql).await?;
+            let mut block = Block::new();
+            block.push(row! {
+                version: *version,
+                name: *name,
+                applied_at: Utc::now().timestamp_millis(),
+            })?;
+            client.insert(MIGRATION_TABLE, block).await?;
+            current = *version;
+        }
+        Ok(current)
+    }
+
+    /// Creates the kline, aggtrade and liquidation tables of `symbol`, plus
+    /// the resampled views configured in `conf`, if they do not exist yet.
+    pub async fn ensure_tables(
+        &self,
+        symbol: &cex_market_symbol::Model,
+        conf: &SchemaConf,
+    ) -> Result<()> {
+        let kline = kline_table(symbol);
+        let mut ddl = vec![
+            kline_ddl(&kline, conf.kline_ttl_days),
+            aggtrade_ddl(&aggtrade_table(symbol), conf.aggtrade_ttl_days),
+            liquidation_ddl(&liquidation_table(symbol), conf.liquidation_ttl_days),
+        ];
+        for interval in conf.resample.iter() {
+            let step = resample_step(&symbol.interval, interval)?;
+            let target = resample_table_name(&symbol.symbol, interval);
+            ddl.extend(resample_ddl(&kline, &target, interval, step));
+        }
+        let mut client = self.db.get_handle().await?;
+        for sql in ddl {
+            client.execute(sql).await?;
+        }
+        Ok(())
+    }
+
+    /// Recomputes, from the rows now in `source`, every resampled bucket
+    /// overlapping `[start, end)` (ms). Views add to their buckets on each
+    /// insert, so rows deleted from `source` must be taken out this way.
+    pub(crate) async fn rebuild_resampled(&self, source: &str, start: i64, end: i64) -> Result<()> {
+        let mut client = self.db.get_handle().await?;
+        let block = client
+            .query(format!(
+                "SELECT arrayJoin(dependencies_table) AS view FROM system.tables
+WHERE database = currentDatabase() AND name = '{}'",
+                source
+            ))
+            .fetch_all()
+            .await?;
+        let views: Vec<String> = block
+            .rows()
+            .map(|row| row.get("view"))
+            .collect::<std::result::Result<_, _>>()?;
+        for view in views.iter() {
+            let Some((target, interval)) = resample_view_target(view) else {
+                continue;
+            };
+            let Some(step) = interval_duration(interval) else {
+                continue;
+            };
+            let (lo, hi) = bucket_range(start, end, step.num_milliseconds());
+            client
+                .execute(format!(
+                    "ALTER TABLE {} DELETE WHERE id >= {} AND id < {} SETTINGS mutations_sync = 1",
+                    target, lo, hi
+                ))
+                .await?;
+            let filter = format!("\nWHERE {}.id >= {} AND {}.id < {}", source, lo, source, hi);
+            client
+                .execute(format!(
+                    "INSERT INTO {} {}",
+                    target,
+                    resample_select(source, interval, step.num_milliseconds(), &filter)
+                ))
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ttl() {
+        assert_eq!(ttl("id", None), "");
+        assert!(kline_ddl("kline_ethusdt_1m", Some(30))
+            .ends_with("ORDER BY id\nTTL toDateTime(intDiv(id, 1000)) + INTERVAL 30 DAY"));
+    }
+
+    #[test]
+    fn test_resample_step() {
+        assert_eq!(resample_step("1m", "1h").unwrap(), 3_600_000);
+        assert_eq!(resample_step("1h", "1d").unwrap(), 86_400_000);
+        // the base itself, spelled either way, a finer interval and a step
+        // that does not split into base candles
+        for (base, interval) in [("1m", "1m"), ("1h", "60m"), ("1h", "1m"), ("2h", "3h")] {
+            assert_eq!(
+                resample_step(base, interval).unwrap_err().reason,
+                Reason::InvalidInterval,
+                "{} over {}",
+                interval,
+                base
+            );
+        }
+        assert!(resample_step("1m", "7x").is_err());
+        assert!(resample_step("7x", "1h").is_err());
+        assert_ne!(
+            resample_table_name("ETHUSDT", "1h"),
+            crate::clickhouse::kline_table_name("ETHUSDT", "1h")
+        );
+    }
+
+    #[test]
+    fn test_resample_rebuild_range() {
+        let target = resample_table_name("ETHUSDT", "1h");
+        let view = format!("{}_mv", target);
+        assert_eq!(resample_view_target(&view), Some((target.as_str(), "1h")));
+        assert_eq!(resample_view_target("kline_ethusdt_1m"), None);
+
+        let hour = 3_600_000;
+        assert_eq!(bucket_range(hour, 2 * hour, hour), (hour, 2 * hour));
+        assert_eq!(bucket_range(hour + 1, 2 * hour + 1, hour), (hour, 3 * hour));
+        assert!(resample_ddl("kline_ethusdt_1m", &target, "1h", hour)[1]
+            .ends_with("FROM kline_ethusdt_1m\nGROUP BY id, symbol"));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a local clickhouse server"]
+    async fn test_migrate() {
+        let db = ClickhouseQuery::new();
+        let first = db.migrate().await.unwrap();
+        let second = db.migrate().await.unwrap();
+        assert_eq!(first, second);
+    }
+}
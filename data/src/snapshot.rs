@@ -0,0 +1,210 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use ecode::Result;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use tokio_graceful::ShutdownGuard;
+
+use crate::{
+    model::{BotAsset, BotPosition, BotStatus},
+    AccountRepo, BotFilter, BotRepo, SnapshotRepo,
+};
+
+/// `cex_bot_asset` at `time` (ms).
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AssetSnapshot {
+    pub time: i64,
+    pub bot_id: i64,
+    pub asset: String,
+    pub wallet_balance: Decimal,
+    pub unrealized_profit: Decimal,
+    pub margin_balance: Decimal,
+    pub available_balance: Decimal,
+    pub initial_margin: Decimal,
+    pub maint_margin: Decimal,
+}
+
+impl AssetSnapshot {
+    pub fn new(time: i64, a: &BotAsset) -> Self {
+        AssetSnapshot {
+            time,
+            bot_id: a.bot_id,
+            asset: a.asset.clone(),
+            wallet_balance: a.wallet_balance,
+            unrealized_profit: a.unrealized_profit,
+            margin_balance: a.margin_balance,
+            available_balance: a.available_balance,
+            initial_margin: a.initial_margin,
+            maint_margin: a.maint_margin,
+        }
+    }
+
+    /// Wallet balance plus unrealized profit.
+    pub fn equity(&self) -> Decimal {
+        self.wallet_balance + self.unrealized_profit
+    }
+}
+
+/// `cex_bot_position` at `time` (ms).
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PositionSnapshot {
+    pub time: i64,
+    pub bot_id: i64,
+    pub symbol: String,
+    pub side: String,
+    pub isolated: bool,
+    pub leverage: Decimal,
+    pub amount: Decimal,
+    pub entry_price: Decimal,
+    pub unrealized_profit: Decimal,
+    pub notional: Decimal,
+    pub initial_margin: Decimal,
+    pub maint_margin: Decimal,
+}
+
+impl PositionSnapshot {
+    pub fn new(time: i64, p: &BotPosition) -> Self {
+        PositionSnapshot {
+            time,
+            bot_id: p.bot_id,
+            symbol: p.symbol.clone(),
+            side: p.side.as_str().to_string(),
+            isolated: p.isolated,
+            leverage: p.leverage,
+            amount: p.amount,
+            entry_price: p.entry_price,
+            unrealized_profit: p.unrealized_profit,
+            notional: p.notional,
+            initial_margin: p.initial_margin,
+            maint_margin: p.maint_margin,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EquityPoint {
+    pub time: i64,
+    pub equity: Decimal,
+}
+
+/// Largest peak to trough fall of an equity curve.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Drawdown {
+    pub peak_time: i64,
+    pub peak: Decimal,
+    pub trough_time: i64,
+    pub trough: Decimal,
+    /// `peak - trough`.
+    pub amount: Decimal,
+    /// `amount / peak`, zero when the peak is not positive.
+    pub ratio: Decimal,
+}
+
+pub fn max_drawdown(curve: &[EquityPoint]) -> Drawdown {
+    let mut res = Drawdown::default();
+    let Some(first) = curve.first() else {
+        return res;
+    };
+    let mut peak = first;
+    for p in curve {
+        if p.equity > peak.equity {
+            peak = p;
+        }
+        let amount = peak.equity - p.equity;
+        if amount > res.amount {
+            res = Drawdown {
+                peak_time: peak.time,
+                peak: peak.equity,
+                trough_time: p.time,
+                trough: p.equity,
+                amount,
+                ratio: if peak.equity > Decimal::ZERO {
+                    amount / peak.equity
+                } else {
+                    Decimal::ZERO
+                },
+            };
+        }
+    }
+    res
+}
+
+/// Copies the current asset and position rows of every running bot into the
+/// snapshot tables, all stamped with the same time. Returns the rows written.
+pub async fn record_snapshots<A, S>(accounts: &A, snapshots: &S) -> Result<usize>
+where
+    A: AccountRepo + BotRepo + Sync,
+    S: SnapshotRepo + Sync,
+{
+    let time = Utc::now().timestamp_millis();
+    let filter = BotFilter {
+        status: Some(BotStatus::Running),
+        ..Default::default()
+    };
+    let mut assets = vec![];
+    let mut positions = vec![];
+    for bot in accounts.list_bots(&filter).await? {
+        for m in accounts.list_bot_assets(bot.id).await? {
+            assets.push(AssetSnapshot::new(time, &BotAsset::try_from(&m)?));
+        }
+        for m in accounts.list_bot_positions(bot.id).await? {
+            positions.push(PositionSnapshot::new(time, &BotPosition::try_from(&m)?));
+        }
+    }
+    snapshots.insert_asset_snapshots(&assets).await?;
+    snapshots.insert_position_snapshots(&positions).await?;
+    Ok(assets.len() + positions.len())
+}
+
+/// Runs `record_snapshots` every `every` until shutdown. Failed rounds are
+/// logged and retried on the next tick.
+pub async fn run_snapshotter<A, S>(accounts: A, snapshots: S, every: Duration, guard: ShutdownGuard)
+where
+    A: AccountRepo + BotRepo + Sync,
+    S: SnapshotRepo + Sync,
+{
+    let mut ticker = tokio::time::interval(every);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+    loop {
+        tokio::select! {
+            _ = guard.cancelled() => break,
+            _ = ticker.tick() => {
+                match record_snapshots(&accounts, &snapshots).await {
+                    Ok(n) => log::debug!("recorded {} account snapshots", n),
+                    Err(e) => log::error!("record account snapshots: {}", e),
+                }
+            }
+        }
+    }
+    log::info!("snapshotter stopping");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn curve(equity: &[Decimal]) -> Vec<EquityPoint> {
+        equity
+            .iter()
+            .enumerate()
+            .map(|(i, e)| EquityPoint {
+                time: i as i64,
+                equity: *e,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_max_drawdown() {
+        let dd = max_drawdown(&curve(&[dec!(100), dec!(120), dec!(90), dec!(130), dec!(110)]));
+        assert_eq!(dd.peak_time, 1);
+        assert_eq!(dd.trough_time, 2);
+        assert_eq!(dd.amount, dec!(30));
+        assert_eq!(dd.ratio, dec!(0.25));
+
+        assert_eq!(max_drawdown(&curve(&[dec!(1), dec!(2)])), Drawdown::default());
+        assert_eq!(max_drawdown(&[]), Drawdown::default());
+    }
+}
@@ -0,0 +1,14 @@
+use data::{query::Query, Conf, Database};
+
+/// A `Query` over a fresh, migrated in-memory sqlite database.
+pub async fn setup() -> Query {
+    let conf = Conf {
+        database: Database {
+            driver: "sqlite".to_string(),
+            source: ":memory:".to_string(),
+            auto_migrate: true,
+        },
+        ..Default::default()
+    };
+    Query::new(&conf).await.unwrap()
+}
@@ -0,0 +1,172 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use common::setup;
+use data::{
+    dec,
+    domain::KlineEvent,
+    import::{ArchiveImporter, CsvConf, ImportReport, EVENT_AGG_TRADE},
+    Decimal, ImportJobRepo, ImportPeriod, KlineRepo,
+};
+use ecode::Result;
+
+#[derive(Clone, Default)]
+struct MemoryKlines {
+    rows: Arc<Mutex<Vec<(String, KlineEvent)>>>,
+}
+
+#[async_trait]
+impl KlineRepo for MemoryKlines {
+    async fn fetch_kline_limit(&self, _table: &str, _limit: u32) -> Result<Vec<KlineEvent>> {
+        Ok(Default::default())
+    }
+
+    async fn fetch_kline_time_limit(
+        &self,
+        _table: &str,
+        _start: &DateTime<Utc>,
+        _to: &DateTime<Utc>,
+    ) -> Result<Vec<KlineEvent>> {
+        Ok(Default::default())
+    }
+
+    async fn fetch_kline_range(
+        &self,
+        _table: &str,
+        _start: &DateTime<Utc>,
+        _to: &DateTime<Utc>,
+    ) -> Result<Vec<KlineEvent>> {
+        Ok(Default::default())
+    }
+
+    async fn fetch_kline_time_limit_open(
+        &self,
+        _table: &str,
+        _start: &DateTime<Utc>,
+        _to: &DateTime<Utc>,
+    ) -> Result<Decimal> {
+        Ok(Default::default())
+    }
+
+    async fn fetch_kline_time_limit_high(
+        &self,
+        _table: &str,
+        _start: &DateTime<Utc>,
+        _to: &DateTime<Utc>,
+    ) -> Result<Decimal> {
+        Ok(Default::default())
+    }
+
+    async fn fetch_kline_time_limit_low(
+        &self,
+        _table: &str,
+        _start: &DateTime<Utc>,
+        _to: &DateTime<Utc>,
+    ) -> Result<Decimal> {
+        Ok(Default::default())
+    }
+
+    async fn fetch_kline_time_limit_close(
+        &self,
+        _table: &str,
+        _start: &DateTime<Utc>,
+        _to: &DateTime<Utc>,
+    ) -> Result<Decimal> {
+        Ok(Default::default())
+    }
+
+    async fn insert_kline(&self, table: &str, kline: KlineEvent) -> Result<()> {
+        self.rows.lock().unwrap().push((table.to_string(), kline));
+        Ok(())
+    }
+
+    async fn delete_klines(&self, table: &str, start: i64, end: i64) -> Result<()> {
+        let mut rows = self.rows.lock().unwrap();
+        rows.retain(|(t, k)| t != table || k.id < start || k.id >= end);
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_kline_import_is_resumable() {
+    let dir = std::env::temp_dir().join(format!("data-import-{}", std::process::id()));
+    let nested = dir.join("futures/um/daily/klines/ETHUSDT/1m");
+    std::fs::create_dir_all(&nested).unwrap();
+    std::fs::write(
+        nested.join("ETHUSDT-1m-2024-01-01.csv"),
+        "open_time,open,high,low,close,volume,close_time,quote_volume,count,\
+         taker_buy_volume,taker_buy_quote_volume,ignore\n\
+         1704067200000,2281.87,2283.00,2281.11,2282.40,1033.9,1704067259999,2359262.1,1874,512.2,1168794.2,0\n\
+         1704067260000,2282.40,2282.41,2280.00,2281.02,712.5,1704067319999,1625230.4,1371,301.7,688136.9,0\n\
+         1704067320000,2281.02,2279.00,2280.50,2280.70,10.0,1704067379999,22807.0,12,1.0,2280.7,0\n",
+    )
+    .unwrap();
+    // Not an archive name, ignored.
+    std::fs::write(nested.join("ETHUSDT-1m-2024-01-01.csv.CHECKSUM"), "x").unwrap();
+
+    let klines = MemoryKlines::default();
+    // left behind by an import that stopped before it was recorded
+    klines.rows.lock().unwrap().push((
+        "kline_ethusdt_1m".to_string(),
+        KlineEvent {
+            id: 1704067200000,
+            ..Default::default()
+        },
+    ));
+    let conf = CsvConf {
+        path: dir.to_string_lossy().to_string(),
+        batch_size: 2,
+        ..Default::default()
+    };
+    let importer = ArchiveImporter::new(setup().await, klines.clone(), conf);
+
+    let report = importer.import_all::<KlineEvent>().await.unwrap();
+    assert_eq!(
+        report,
+        ImportReport {
+            files: 1,
+            rows: 2,
+            // high below open
+            rejected: 1,
+            ..Default::default()
+        }
+    );
+    {
+        let rows = klines.rows.lock().unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].0, "kline_ethusdt_1m");
+        assert_eq!(rows[0].1.exchange, "binance");
+        assert_eq!(rows[1].1.close, dec!(2281.02));
+    }
+
+    let report = importer.import_all::<KlineEvent>().await.unwrap();
+    assert_eq!(report.skipped, 1);
+    assert_eq!(report.files, 0);
+    assert_eq!(klines.rows.lock().unwrap().len(), 2);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_import_jobs_are_per_market() {
+    let q = setup().await;
+    let spot = ImportPeriod {
+        exchange: "binance".to_string(),
+        market: "spot".to_string(),
+        event: EVENT_AGG_TRADE.to_string(),
+        symbol: "BTCUSDT".to_string(),
+        interval: String::new(),
+        start_time: 1_704_067_200_000,
+        end_time: 1_704_153_600_000,
+    };
+    let futures = ImportPeriod {
+        market: "futures".to_string(),
+        ..spot.clone()
+    };
+    q.record_import(&spot).await.unwrap();
+    assert!(q.import_covered(&spot).await.unwrap());
+    assert!(!q.import_covered(&futures).await.unwrap());
+}
@@ -0,0 +1,124 @@
+mod common;
+
+use chrono::{Duration, Utc};
+use common::setup;
+use data::{
+    dec,
+    entity::{cex_bot_order, cex_bot_sell_order, prelude::CexBotOrder, prelude::CexBotSellOrder},
+    ledger::{FeeConf, LotMethod},
+    model::{
+        AOrderStatus, BotSellOrder, ExecutionType, OrderReport, OrderSide, OrderStatus, OrderType,
+        TimeInForce,
+    },
+    BotRepo, Decimal, LedgerRepo, NewBot,
+};
+use ecode::Reason;
+use sea_orm::{EntityTrait, Set};
+
+fn buy(bot_id: i64, order_id: i64, qty: Decimal, status: OrderStatus) -> OrderReport {
+    OrderReport {
+        bot_id,
+        symbol: "ETHUSDT".to_string(),
+        order_id,
+        side: OrderSide::Buy,
+        ty: OrderType::Limit,
+        time_in_force: TimeInForce::Gtc,
+        original_qty: qty,
+        original_price: dec!(100) + Decimal::from(order_id),
+        average_price: dec!(0),
+        stop_price: dec!(0),
+        execution_type: ExecutionType::Trade,
+        status,
+    }
+}
+
+fn sell(bot_id: i64, id: i64, qty: Decimal, status: AOrderStatus) -> BotSellOrder {
+    BotSellOrder {
+        id,
+        bot_id,
+        symbol: "ETHUSDT".to_string(),
+        order_id: id,
+        status: status.into(),
+        price: dec!(120),
+        quantity: qty,
+        real_price: Some(dec!(120)),
+        real_quantity: Some(qty),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn test_settle_profit_and_pnl_summary() {
+    let q = setup().await;
+    let bot = q
+        .create_bot(NewBot {
+            exchange: "binance".to_string(),
+            market: "spot".to_string(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    let buys = [
+        buy(bot.id, 1, dec!(1), OrderStatus::Filled),
+        // filled with nothing, must not become a lot
+        buy(bot.id, 2, dec!(0), OrderStatus::Filled),
+        buy(bot.id, 3, dec!(1), OrderStatus::Filled),
+        buy(bot.id, 4, dec!(1), OrderStatus::New),
+    ];
+    for b in buys.iter() {
+        let am: cex_bot_order::ActiveModel = b.into();
+        CexBotOrder::insert(am)
+            .exec_without_returning(q.conn())
+            .await
+            .unwrap();
+    }
+    let now = Utc::now().naive_utc();
+    let sells = [
+        sell(bot.id, 10, dec!(1), AOrderStatus::Filled),
+        sell(bot.id, 11, dec!(1), AOrderStatus::Canceled),
+        // more than every open lot
+        sell(bot.id, 12, dec!(5), AOrderStatus::Filled),
+    ];
+    for s in sells.iter() {
+        let mut am: cex_bot_sell_order::ActiveModel = s.into();
+        am.created_at = Set(Some(now));
+        CexBotSellOrder::insert(am)
+            .exec_without_returning(q.conn())
+            .await
+            .unwrap();
+    }
+
+    let fees = FeeConf::default();
+    let err = q
+        .settle_profit(bot.id, "ETHUSDT", LotMethod::Fifo, &fees)
+        .await
+        .unwrap_err();
+    assert_eq!(err.reason, Reason::LedgerUnmatched);
+    // the sell settled before the failure is rolled back
+    let stored = CexBotSellOrder::find_by_id(10)
+        .one(q.conn())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(stored.profit, None);
+
+    CexBotSellOrder::delete_by_id(12)
+        .exec(q.conn())
+        .await
+        .unwrap();
+    let realized = q
+        .settle_profit(bot.id, "ETHUSDT", LotMethod::Fifo, &fees)
+        .await
+        .unwrap();
+    assert_eq!(realized.len(), 1);
+    assert_eq!(realized[0].sell_id, 10);
+    assert_eq!(realized[0].cost, dec!(101));
+    assert_eq!(realized[0].profit, dec!(19));
+
+    let start = Utc::now() - Duration::hours(1);
+    let to = Utc::now() + Duration::hours(1);
+    let summary = q.pnl_summary(bot.id, &start, &to).await.unwrap();
+    assert_eq!(summary.sells, 1);
+    assert_eq!(summary.wins, 1);
+    assert_eq!(summary.profit, dec!(19));
+}
@@ -0,0 +1,73 @@
+mod common;
+
+use common::setup;
+use data::{model::BotStatus, BotFilter, BotRepo, NewBot};
+use ecode::Reason;
+
+fn new_bot(exchange: &str, tag: &str) -> NewBot {
+    NewBot {
+        exchange: exchange.to_string(),
+        market: "futures".to_string(),
+        key: "key".to_string(),
+        secret: "secret".to_string(),
+        tag: tag.to_string(),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn test_bot_lifecycle() {
+    let q = setup().await;
+    let bot = q.create_bot(new_bot("binance", "grid")).await.unwrap();
+    assert_eq!(bot.status, i32::from(BotStatus::Created));
+
+    let err = q.pause_bot(bot.id).await.unwrap_err();
+    assert_eq!(err.reason, Reason::InvalidBotTransition);
+
+    let bot = q.resume_bot(bot.id).await.unwrap();
+    assert_eq!(bot.status, i32::from(BotStatus::Running));
+    let bot = q.pause_bot(bot.id).await.unwrap();
+    assert_eq!(bot.status, i32::from(BotStatus::Paused));
+    let bot = q.resume_bot(bot.id).await.unwrap();
+    let bot = q.stop_bot(bot.id).await.unwrap();
+    assert_eq!(bot.status, i32::from(BotStatus::Stopped));
+
+    let err = q.resume_bot(bot.id).await.unwrap_err();
+    assert_eq!(err.reason, Reason::InvalidBotTransition);
+    let err = q.stop_bot(bot.id).await.unwrap_err();
+    assert_eq!(err.reason, Reason::InvalidBotTransition);
+
+    let err = q.get_bot(bot.id + 1).await.unwrap_err();
+    assert_eq!(err.reason, Reason::BotNotFound);
+}
+
+#[tokio::test]
+async fn test_list_bots_and_rotate_credentials() {
+    let q = setup().await;
+    let grid = q.create_bot(new_bot("binance", "grid")).await.unwrap();
+    q.create_bot(new_bot("okex", "grid")).await.unwrap();
+    q.create_bot(new_bot("binance", "dca")).await.unwrap();
+    q.resume_bot(grid.id).await.unwrap();
+
+    let filter = BotFilter {
+        tag: Some("grid".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(q.list_bots(&filter).await.unwrap().len(), 2);
+    let filter = BotFilter {
+        exchange: Some("binance".to_string()),
+        status: Some(BotStatus::Running),
+        ..Default::default()
+    };
+    let running = q.list_bots(&filter).await.unwrap();
+    assert_eq!(running.len(), 1);
+    assert_eq!(running[0].id, grid.id);
+
+    let bot = q
+        .rotate_bot_credentials(grid.id, "key2", "secret2", "")
+        .await
+        .unwrap();
+    assert_eq!(bot.key, "key2");
+    assert_eq!(bot.secret, "secret2");
+    assert_eq!(bot.status, i32::from(BotStatus::Running));
+}
@@ -0,0 +1,193 @@
+mod common;
+
+use chrono::Utc;
+use common::setup;
+use data::{
+    entity::{cex_market, cex_market_symbol},
+    model::{STATUS_DISABLED, STATUS_ENABLED},
+    query::Query,
+    MarketFilter, NewMarket, NewMarketSymbol, QueryMarketDao,
+};
+use ecode::Reason;
+use sea_orm::{ActiveModelTrait, Set};
+
+async fn insert_market(q: &Query, exchange: &str, market: &str, status: i32) {
+    let now = Utc::now().naive_utc();
+    cex_market::ActiveModel {
+        exchange: Set(exchange.to_string()),
+        market: Set(market.to_string()),
+        key: Set(String::new()),
+        secret: Set(String::new()),
+        passphrase: Set(String::new()),
+        status: Set(status),
+        created_at: Set(now),
+        updated_at: Set(now),
+        ..Default::default()
+    }
+    .insert(q.conn())
+    .await
+    .unwrap();
+}
+
+async fn insert_symbol(q: &Query, exchange: &str, market: &str, symbol: &str, status: i32) {
+    let now = Utc::now().naive_utc();
+    cex_market_symbol::ActiveModel {
+        exchange: Set(exchange.to_string()),
+        market: Set(market.to_string()),
+        symbol: Set(symbol.to_string()),
+        interval: Set("1m".to_string()),
+        base: Set(symbol.trim_end_matches("USDT").to_string()),
+        quote: Set("USDT".to_string()),
+        status: Set(status),
+        created_at: Set(now),
+        updated_at: Set(now),
+        ..Default::default()
+    }
+    .insert(q.conn())
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn test_batch_get_market_by_status() {
+    let q = setup().await;
+    insert_market(&q, "binance", "futures", 1).await;
+    insert_market(&q, "okex", "futures", 0).await;
+
+    let res = q.batch_get_market_by_status().await.unwrap();
+    assert_eq!(res.len(), 1);
+    assert_eq!(res[0].exchange, "binance");
+}
+
+#[tokio::test]
+async fn test_batch_get_market_symbol_by_status() {
+    let q = setup().await;
+    insert_symbol(&q, "binance", "futures", "ETHUSDT", 1).await;
+    insert_symbol(&q, "binance", "futures", "BTCUSDT", 0).await;
+    insert_symbol(&q, "binance", "spot", "ETHUSDT", 1).await;
+    insert_symbol(&q, "okex", "futures", "ETHUSDT", 1).await;
+
+    let res = q
+        .batch_get_market_symbol_by_status("binance", "futures")
+        .await
+        .unwrap();
+    assert_eq!(res.len(), 1);
+    assert_eq!(res[0].symbol, "ETHUSDT");
+    assert_eq!(res[0].market, "futures");
+}
+
+fn new_market(exchange: &str, market: &str) -> NewMarket {
+    NewMarket {
+        exchange: exchange.to_string(),
+        market: market.to_string(),
+        key: "key".to_string(),
+        ..Default::default()
+    }
+}
+
+fn new_symbol(symbol: &str) -> NewMarketSymbol {
+    NewMarketSymbol {
+        exchange: "binance".to_string(),
+        market: "futures".to_string(),
+        symbol: symbol.to_string(),
+        interval: "1m".to_string(),
+        base: symbol.trim_end_matches("USDT").to_string(),
+        quote: "USDT".to_string(),
+    }
+}
+
+#[tokio::test]
+async fn test_market_crud() {
+    let q = setup().await;
+    let m = q.create_market(new_market("binance", "futures")).await.unwrap();
+    assert_eq!(m.status, STATUS_DISABLED);
+
+    let err = q
+        .create_market(new_market("binance", "futures"))
+        .await
+        .unwrap_err();
+    assert_eq!(err.reason, Reason::MarketExists);
+
+    q.set_market_status(m.id, STATUS_ENABLED).await.unwrap();
+    assert_eq!(q.batch_get_market_by_status().await.unwrap().len(), 1);
+
+    let other = q.create_market(new_market("okex", "futures")).await.unwrap();
+    let err = q
+        .update_market(other.id, new_market("binance", "futures"))
+        .await
+        .unwrap_err();
+    assert_eq!(err.reason, Reason::MarketExists);
+    let other = q
+        .update_market(other.id, new_market("okex", "spot"))
+        .await
+        .unwrap();
+    assert_eq!(other.market, "spot");
+
+    let filter = MarketFilter {
+        exchange: Some("okex".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(q.list_markets(&filter).await.unwrap().len(), 1);
+
+    q.delete_market(m.id).await.unwrap();
+    assert!(q.get_market("binance", "futures").await.unwrap().is_none());
+    assert!(q.batch_get_market_by_status().await.unwrap().is_empty());
+    let err = q.set_market_status(m.id, STATUS_ENABLED).await.unwrap_err();
+    assert_eq!(err.reason, Reason::MarketNotFound);
+
+    let revived = q.create_market(new_market("binance", "futures")).await.unwrap();
+    assert_eq!(revived.id, m.id);
+    assert!(revived.deleted_at.is_none());
+    assert_eq!(q.list_markets(&MarketFilter::default()).await.unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn test_market_symbol_crud() {
+    let q = setup().await;
+    let eth = q.create_market_symbol(new_symbol("ETHUSDT")).await.unwrap();
+    let btc = q.create_market_symbol(new_symbol("BTCUSDT")).await.unwrap();
+
+    let err = q
+        .create_market_symbol(new_symbol("ETHUSDT"))
+        .await
+        .unwrap_err();
+    assert_eq!(err.reason, Reason::MarketSymbolExists);
+    let err = q
+        .update_market_symbol(btc.id, new_symbol("ETHUSDT"))
+        .await
+        .unwrap_err();
+    assert_eq!(err.reason, Reason::MarketSymbolExists);
+
+    let mut update = new_symbol("ETHUSDT");
+    update.interval = "5m".to_string();
+    let eth = q.update_market_symbol(eth.id, update).await.unwrap();
+    assert_eq!(eth.interval, "5m");
+
+    q.set_market_symbol_status(eth.id, STATUS_ENABLED).await.unwrap();
+    let enabled = q
+        .batch_get_market_symbol_by_status("binance", "futures")
+        .await
+        .unwrap();
+    assert_eq!(enabled.len(), 1);
+    assert_eq!(enabled[0].id, eth.id);
+
+    let filter = MarketFilter {
+        symbol: Some("BTCUSDT".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(q.list_market_symbols(&filter).await.unwrap()[0].id, btc.id);
+
+    q.delete_market_symbol(eth.id).await.unwrap();
+    assert!(q
+        .get_market_symbol("binance", "futures", "ETHUSDT")
+        .await
+        .unwrap()
+        .is_none());
+    let err = q.delete_market_symbol(eth.id).await.unwrap_err();
+    assert_eq!(err.reason, Reason::MarketSymbolNotFound);
+    assert!(q
+        .batch_get_market_symbol_by_status("binance", "futures")
+        .await
+        .unwrap()
+        .is_empty());
+}
@@ -0,0 +1,128 @@
+mod common;
+
+use chrono::Utc;
+use common::setup;
+use data::{
+    dec,
+    entity::{cex_bot_order, prelude::CexBotOrder},
+    model::{ExecutionType, OrderReport, OrderSide, OrderStatus, OrderType, TimeInForce},
+    OrderRepo,
+};
+use sea_orm::{sea_query::Expr, ColumnTrait, EntityTrait, QueryFilter};
+
+fn report(order_id: i64, status: OrderStatus) -> OrderReport {
+    OrderReport {
+        bot_id: 1,
+        symbol: "ETHUSDT".to_string(),
+        order_id,
+        side: OrderSide::Buy,
+        ty: OrderType::Limit,
+        time_in_force: TimeInForce::Gtc,
+        original_qty: dec!(0.5),
+        original_price: dec!(2000.10),
+        average_price: dec!(0),
+        stop_price: dec!(0),
+        execution_type: ExecutionType::New,
+        status,
+    }
+}
+
+#[tokio::test]
+async fn test_upsert_order() {
+    let q = setup().await;
+    let new = report(100, OrderStatus::New);
+    let stored = q.upsert_order(&new).await.unwrap();
+    assert_eq!(stored.status, "NEW");
+    assert_eq!(stored.original_price, "2000.10");
+
+    // replaying the same report keeps a single, untouched row
+    let again = q.upsert_order(&new).await.unwrap();
+    assert_eq!(again, stored);
+
+    let mut filled = report(100, OrderStatus::Filled);
+    filled.execution_type = ExecutionType::Trade;
+    filled.average_price = dec!(1999.5);
+    let stored = q.upsert_order(&filled).await.unwrap();
+    assert_eq!(stored.status, "FILLED");
+    assert_eq!(stored.average_price, "1999.5");
+
+    // a late NEW report must not reopen the order
+    let stored = q.upsert_order(&new).await.unwrap();
+    assert_eq!(stored.status, "FILLED");
+    assert_eq!(OrderReport::try_from(&stored).unwrap(), filled);
+
+    // nor may a late terminal report replace it
+    let mut canceled = report(100, OrderStatus::Canceled);
+    canceled.execution_type = ExecutionType::Canceled;
+    let stored = q.upsert_order(&canceled).await.unwrap();
+    assert_eq!(OrderReport::try_from(&stored).unwrap(), filled);
+
+    assert!(q.list_open_orders(1).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_upsert_order_revives_deleted() {
+    let q = setup().await;
+    let stored = q
+        .upsert_order(&report(7, OrderStatus::Filled))
+        .await
+        .unwrap();
+    CexBotOrder::update_many()
+        .col_expr(
+            cex_bot_order::Column::DeletedAt,
+            Expr::value(Utc::now().naive_utc()),
+        )
+        .filter(cex_bot_order::Column::Id.eq(stored.id))
+        .exec(q.conn())
+        .await
+        .unwrap();
+    assert_eq!(q.get_order(1, 7).await.unwrap(), None);
+
+    let revived = q.upsert_order(&report(7, OrderStatus::New)).await.unwrap();
+    assert_eq!(revived.id, stored.id);
+    assert_eq!(revived.status, "NEW");
+    assert_eq!(revived.deleted_at, None);
+    assert_eq!(q.get_order(1, 7).await.unwrap(), Some(revived));
+}
+
+#[tokio::test]
+async fn test_upsert_order_concurrent() {
+    let q = setup().await;
+    let new = report(9, OrderStatus::New);
+    let filled = report(9, OrderStatus::Filled);
+    let (a, b) = tokio::join!(q.upsert_order(&new), q.upsert_order(&filled));
+    assert_eq!(a.unwrap().id, b.unwrap().id);
+    let stored = q.get_order(1, 9).await.unwrap().unwrap();
+    assert_eq!(stored.status, "FILLED");
+    assert_eq!(CexBotOrder::find().all(q.conn()).await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_reconcile_orders() {
+    let q = setup().await;
+    q.upsert_order(&report(1, OrderStatus::New)).await.unwrap();
+    q.upsert_order(&report(2, OrderStatus::New)).await.unwrap();
+    q.upsert_order(&report(3, OrderStatus::Filled))
+        .await
+        .unwrap();
+
+    let in_sync = vec![report(1, OrderStatus::New), report(2, OrderStatus::New)];
+    assert!(q.reconcile_orders(1, &in_sync).await.unwrap().is_empty());
+
+    let partial = report(2, OrderStatus::PartiallyFilled);
+    let snapshot = vec![
+        partial.clone(),
+        report(3, OrderStatus::New),
+        report(4, OrderStatus::New),
+    ];
+    let drift = q.reconcile_orders(1, &snapshot).await.unwrap();
+    assert_eq!(drift.missing_on_exchange, vec![report(1, OrderStatus::New)]);
+    assert_eq!(
+        drift.unknown_locally,
+        vec![report(3, OrderStatus::New), report(4, OrderStatus::New)]
+    );
+    assert_eq!(
+        drift.mismatched,
+        vec![(report(2, OrderStatus::New), partial)]
+    );
+}
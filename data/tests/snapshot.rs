@@ -0,0 +1,146 @@
+mod common;
+
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use common::setup;
+use data::{
+    dec,
+    entity::cex_bot_asset,
+    snapshot::{record_snapshots, AssetSnapshot, Drawdown, EquityPoint, PositionSnapshot},
+    BotRepo, Decimal, NewBot, SnapshotRepo,
+};
+use ecode::Result;
+use sea_orm::{ActiveModelTrait, Set};
+
+#[derive(Default)]
+struct MemorySnapshots {
+    assets: Mutex<Vec<AssetSnapshot>>,
+    positions: Mutex<Vec<PositionSnapshot>>,
+}
+
+#[async_trait]
+impl SnapshotRepo for MemorySnapshots {
+    async fn insert_asset_snapshots(&self, rows: &[AssetSnapshot]) -> Result<()> {
+        self.assets.lock().unwrap().extend_from_slice(rows);
+        Ok(())
+    }
+
+    async fn insert_position_snapshots(&self, rows: &[PositionSnapshot]) -> Result<()> {
+        self.positions.lock().unwrap().extend_from_slice(rows);
+        Ok(())
+    }
+
+    async fn fetch_equity_curve(
+        &self,
+        bot_id: i64,
+        asset: &str,
+        start: &DateTime<Utc>,
+        to: &DateTime<Utc>,
+    ) -> Result<Vec<EquityPoint>> {
+        let (start, to) = (start.timestamp_millis(), to.timestamp_millis());
+        let mut curve: Vec<_> = self
+            .assets
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|a| a.bot_id == bot_id && a.asset == asset)
+            .filter(|a| a.time >= start && a.time < to)
+            .map(|a| EquityPoint {
+                time: a.time,
+                equity: a.equity(),
+            })
+            .collect();
+        curve.sort_by_key(|p| p.time);
+        Ok(curve)
+    }
+}
+
+#[tokio::test]
+async fn test_record_snapshots() {
+    let q = setup().await;
+    let running = q.create_bot(NewBot::default()).await.unwrap();
+    q.resume_bot(running.id).await.unwrap();
+    let idle = q.create_bot(NewBot::default()).await.unwrap();
+
+    let now = Utc::now().naive_utc();
+    for bot_id in [running.id, idle.id] {
+        cex_bot_asset::ActiveModel {
+            bot_id: Set(bot_id),
+            asset: Set("USDT".to_string()),
+            initial_margin: Set("0".to_string()),
+            maint_margin: Set("0".to_string()),
+            margin_balance: Set("1010.5".to_string()),
+            open_order_initial_margin: Set("0".to_string()),
+            position_initial_margin: Set("0".to_string()),
+            unrealized_profit: Set("10.5".to_string()),
+            wallet_balance: Set("1000".to_string()),
+            cross_wallet_balance: Set("1000".to_string()),
+            cross_un_pnl: Set("10.5".to_string()),
+            available_balance: Set("900".to_string()),
+            created_at: Set(now),
+            updated_at: Set(now),
+            ..Default::default()
+        }
+        .insert(q.conn())
+        .await
+        .unwrap();
+    }
+
+    let snapshots = MemorySnapshots::default();
+    assert_eq!(record_snapshots(&q, &snapshots).await.unwrap(), 1);
+    let assets = snapshots.assets.lock().unwrap().clone();
+    assert_eq!(assets[0].bot_id, running.id);
+    assert_eq!(assets[0].equity(), dec!(1010.5));
+}
+
+#[tokio::test]
+async fn test_fetch_drawdown() {
+    let snapshots = MemorySnapshots::default();
+    let minute = 60_000;
+    let asset = |time: i64, equity: Decimal| AssetSnapshot {
+        time,
+        bot_id: 1,
+        asset: "USDT".to_string(),
+        wallet_balance: equity,
+        unrealized_profit: dec!(0),
+        margin_balance: equity,
+        available_balance: equity,
+        initial_margin: dec!(0),
+        maint_margin: dec!(0),
+    };
+    // out of order, and the deepest fall is after the range
+    let rows = [
+        asset(2 * minute, dec!(900)),
+        asset(0, dec!(1000)),
+        asset(minute, dec!(1200)),
+        asset(3 * minute, dec!(1100)),
+        asset(4 * minute, dec!(500)),
+    ];
+    snapshots.insert_asset_snapshots(&rows).await.unwrap();
+
+    let start = DateTime::from_timestamp_millis(0).unwrap();
+    let to = DateTime::from_timestamp_millis(4 * minute).unwrap();
+    let drawdown = snapshots
+        .fetch_drawdown(1, "USDT", &start, &to)
+        .await
+        .unwrap();
+    assert_eq!(
+        drawdown,
+        Drawdown {
+            peak_time: minute,
+            peak: dec!(1200),
+            trough_time: 2 * minute,
+            trough: dec!(900),
+            amount: dec!(300),
+            ratio: dec!(0.25),
+        }
+    );
+
+    let empty = snapshots
+        .fetch_drawdown(2, "USDT", &start, &to)
+        .await
+        .unwrap();
+    assert_eq!(empty, Drawdown::default());
+}
@@ -45,6 +45,39 @@ pub enum Reason {
     AssetNotFoundError,
     SellFailed,
     BuyFailed,
+    InvalidInterval,
+    MarketExists,
+    MarketNotFound,
+    MarketSymbolExists,
+    MarketSymbolNotFound,
+    BotNotFound,
+    InvalidBotTransition,
+    InvalidOrder,
+    LedgerUnmatched,
+    DecimalOverflow,
+    InvalidDecimal,
+    InvalidEvent,
+    UnsupportedEventVersion,
+    DepthOutOfSync,
+    LockTimeout,
+    ClockMovedBackwards,
+    InvalidWorkerId,
+    WorkerIdUnavailable,
+    InvalidArchive,
+    UnsupportedExportFormat,
+    InvalidAsset,
+    InvalidOrderIds,
+}
+
+impl Status {
+    pub fn new(code: i32, reason: Reason, message: impl Into<String>) -> Self {
+        Status {
+            code,
+            reason,
+            message: message.into(),
+            metadata: Default::default(),
+        }
+    }
 }
 
 impl PartialEq for Status {
@@ -0,0 +1,32 @@
+pub use sea_orm_migration::prelude::*;
+
+mod m20240101_000001_create_cex_market;
+mod m20240101_000002_create_cex_market_symbol;
+mod m20240101_000003_create_cex_bot;
+mod m20240101_000004_create_cex_bot_order;
+mod m20240101_000005_create_cex_bot_sell_order;
+mod m20240101_000006_create_cex_bot_asset;
+mod m20240101_000007_create_cex_bot_position;
+mod m20240101_000008_create_cex_binance_kline_csv;
+mod m20240101_000009_alter_order_id_signed;
+mod m20240101_000010_add_cex_binance_kline_csv_exchange;
+
+pub struct Migrator;
+
+#[async_trait::async_trait]
+impl MigratorTrait for Migrator {
+    fn migrations() -> Vec<Box<dyn MigrationTrait>> {
+        vec![
+            Box::new(m20240101_000001_create_cex_market::Migration),
+            Box::new(m20240101_000002_create_cex_market_symbol::Migration),
+            Box::new(m20240101_000003_create_cex_bot::Migration),
+            Box::new(m20240101_000004_create_cex_bot_order::Migration),
+            Box::new(m20240101_000005_create_cex_bot_sell_order::Migration),
+            Box::new(m20240101_000006_create_cex_bot_asset::Migration),
+            Box::new(m20240101_000007_create_cex_bot_position::Migration),
+            Box::new(m20240101_000008_create_cex_binance_kline_csv::Migration),
+            Box::new(m20240101_000009_alter_order_id_signed::Migration),
+            Box::new(m20240101_000010_add_cex_binance_kline_csv_exchange::Migration),
+        ]
+    }
+}
@@ -0,0 +1,77 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(CexMarket::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(CexMarket::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(CexMarket::Exchange).string_len(32).not_null())
+                    .col(ColumnDef::new(CexMarket::Market).string_len(32).not_null())
+                    .col(ColumnDef::new(CexMarket::Key).string_len(255).not_null().default(""))
+                    .col(ColumnDef::new(CexMarket::Secret).string_len(255).not_null().default(""))
+                    .col(ColumnDef::new(CexMarket::Passphrase).string_len(255).not_null().default(""))
+                    .col(ColumnDef::new(CexMarket::Status).integer().not_null().default(0))
+                    .col(
+                        ColumnDef::new(CexMarket::CreatedAt)
+                            .date_time()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .col(
+                        ColumnDef::new(CexMarket::UpdatedAt)
+                            .date_time()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .col(ColumnDef::new(CexMarket::DeletedAt).date_time().null())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_cex_market_exchange_market")
+                    .table(CexMarket::Table)
+                    .col(CexMarket::Exchange)
+                    .col(CexMarket::Market)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(CexMarket::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum CexMarket {
+    Table,
+    Id,
+    Exchange,
+    Market,
+    Key,
+    Secret,
+    Passphrase,
+    Status,
+    CreatedAt,
+    UpdatedAt,
+    DeletedAt,
+}
@@ -0,0 +1,80 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(CexMarketSymbol::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(CexMarketSymbol::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(CexMarketSymbol::Exchange).string_len(32).not_null())
+                    .col(ColumnDef::new(CexMarketSymbol::Market).string_len(32).not_null())
+                    .col(ColumnDef::new(CexMarketSymbol::Symbol).string_len(32).not_null())
+                    .col(ColumnDef::new(CexMarketSymbol::Interval).string_len(8).not_null())
+                    .col(ColumnDef::new(CexMarketSymbol::Base).string_len(16).not_null())
+                    .col(ColumnDef::new(CexMarketSymbol::Quote).string_len(16).not_null())
+                    .col(ColumnDef::new(CexMarketSymbol::Status).integer().not_null().default(0))
+                    .col(
+                        ColumnDef::new(CexMarketSymbol::CreatedAt)
+                            .date_time()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .col(
+                        ColumnDef::new(CexMarketSymbol::UpdatedAt)
+                            .date_time()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .col(ColumnDef::new(CexMarketSymbol::DeletedAt).date_time().null())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_cex_market_symbol_exchange_market_symbol")
+                    .table(CexMarketSymbol::Table)
+                    .col(CexMarketSymbol::Exchange)
+                    .col(CexMarketSymbol::Market)
+                    .col(CexMarketSymbol::Symbol)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(CexMarketSymbol::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum CexMarketSymbol {
+    Table,
+    Id,
+    Exchange,
+    Market,
+    Symbol,
+    Interval,
+    Base,
+    Quote,
+    Status,
+    CreatedAt,
+    UpdatedAt,
+    DeletedAt,
+}
@@ -0,0 +1,77 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(CexBot::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(CexBot::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(CexBot::Exchange).string_len(32).not_null())
+                    .col(ColumnDef::new(CexBot::Market).string_len(32).not_null())
+                    .col(ColumnDef::new(CexBot::Key).string_len(255).not_null())
+                    .col(ColumnDef::new(CexBot::Secret).string_len(255).not_null())
+                    .col(ColumnDef::new(CexBot::Passphrase).string_len(255).not_null().default(""))
+                    .col(ColumnDef::new(CexBot::Tag).string_len(64).not_null().default(""))
+                    .col(ColumnDef::new(CexBot::Status).integer().not_null().default(0))
+                    .col(
+                        ColumnDef::new(CexBot::CreatedAt)
+                            .date_time()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .col(
+                        ColumnDef::new(CexBot::UpdatedAt)
+                            .date_time()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .col(ColumnDef::new(CexBot::DeletedAt).date_time().null())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_cex_bot_tag")
+                    .table(CexBot::Table)
+                    .col(CexBot::Tag)
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(CexBot::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum CexBot {
+    Table,
+    Id,
+    Exchange,
+    Market,
+    Key,
+    Secret,
+    Passphrase,
+    Tag,
+    Status,
+    CreatedAt,
+    UpdatedAt,
+    DeletedAt,
+}
@@ -0,0 +1,89 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(CexBotOrder::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(CexBotOrder::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(CexBotOrder::BotId).big_integer().not_null())
+                    .col(ColumnDef::new(CexBotOrder::Ty).string_len(32).not_null())
+                    .col(ColumnDef::new(CexBotOrder::Side).string_len(8).not_null())
+                    .col(ColumnDef::new(CexBotOrder::Symbol).string_len(32).not_null())
+                    .col(ColumnDef::new(CexBotOrder::OrderId).big_unsigned().not_null())
+                    .col(ColumnDef::new(CexBotOrder::TimeInForce).string_len(8).not_null())
+                    .col(ColumnDef::new(CexBotOrder::OriginalQty).string_len(64).not_null())
+                    .col(ColumnDef::new(CexBotOrder::OriginalPrice).string_len(64).not_null())
+                    .col(ColumnDef::new(CexBotOrder::AveragePrice).string_len(64).not_null())
+                    .col(ColumnDef::new(CexBotOrder::StopPrice).string_len(64).not_null())
+                    .col(ColumnDef::new(CexBotOrder::ExecutionType).string_len(32).not_null())
+                    .col(ColumnDef::new(CexBotOrder::Status).string_len(32).not_null())
+                    .col(
+                        ColumnDef::new(CexBotOrder::CreatedAt)
+                            .date_time()
+                            .null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .col(
+                        ColumnDef::new(CexBotOrder::UpdatedAt)
+                            .date_time()
+                            .null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .col(ColumnDef::new(CexBotOrder::DeletedAt).date_time().null())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_cex_bot_order_bot_id_order_id")
+                    .table(CexBotOrder::Table)
+                    .col(CexBotOrder::BotId)
+                    .col(CexBotOrder::OrderId)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(CexBotOrder::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum CexBotOrder {
+    Table,
+    Id,
+    BotId,
+    Ty,
+    Side,
+    Symbol,
+    OrderId,
+    TimeInForce,
+    OriginalQty,
+    OriginalPrice,
+    AveragePrice,
+    StopPrice,
+    ExecutionType,
+    Status,
+    CreatedAt,
+    UpdatedAt,
+    DeletedAt,
+}
@@ -0,0 +1,94 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(CexBotSellOrder::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(CexBotSellOrder::Id)
+                            .big_integer()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(CexBotSellOrder::BotId).big_integer().not_null())
+                    .col(ColumnDef::new(CexBotSellOrder::Ty).integer().not_null())
+                    .col(ColumnDef::new(CexBotSellOrder::Symbol).string_len(32).not_null())
+                    .col(ColumnDef::new(CexBotSellOrder::ClientOrderId).string_len(64).not_null())
+                    .col(ColumnDef::new(CexBotSellOrder::OrderId).big_unsigned().not_null())
+                    .col(ColumnDef::new(CexBotSellOrder::Status).integer().not_null())
+                    .col(ColumnDef::new(CexBotSellOrder::Price).string_len(64).not_null())
+                    .col(ColumnDef::new(CexBotSellOrder::Quantity).string_len(64).not_null())
+                    .col(ColumnDef::new(CexBotSellOrder::RealPrice).string_len(64).null())
+                    .col(ColumnDef::new(CexBotSellOrder::RealQuantity).string_len(64).null())
+                    .col(ColumnDef::new(CexBotSellOrder::RealQuoteQty).string_len(64).null())
+                    .col(ColumnDef::new(CexBotSellOrder::QuoteFee).string_len(64).null())
+                    .col(ColumnDef::new(CexBotSellOrder::CancelOrderId).big_unsigned().null())
+                    .col(ColumnDef::new(CexBotSellOrder::CancelOrderStatus).integer().null())
+                    .col(ColumnDef::new(CexBotSellOrder::BuyOrderIds).json().not_null())
+                    .col(ColumnDef::new(CexBotSellOrder::Profit).string_len(64).null())
+                    .col(
+                        ColumnDef::new(CexBotSellOrder::CreatedAt)
+                            .date_time()
+                            .null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .col(
+                        ColumnDef::new(CexBotSellOrder::UpdatedAt)
+                            .date_time()
+                            .null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .col(ColumnDef::new(CexBotSellOrder::DeletedAt).date_time().null())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_cex_bot_sell_order_bot_id")
+                    .table(CexBotSellOrder::Table)
+                    .col(CexBotSellOrder::BotId)
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(CexBotSellOrder::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum CexBotSellOrder {
+    Table,
+    Id,
+    BotId,
+    Ty,
+    Symbol,
+    ClientOrderId,
+    OrderId,
+    Status,
+    Price,
+    Quantity,
+    RealPrice,
+    RealQuantity,
+    RealQuoteQty,
+    QuoteFee,
+    CancelOrderId,
+    CancelOrderStatus,
+    BuyOrderIds,
+    Profit,
+    CreatedAt,
+    UpdatedAt,
+    DeletedAt,
+}
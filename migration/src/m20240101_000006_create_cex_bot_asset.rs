@@ -0,0 +1,91 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(CexBotAsset::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(CexBotAsset::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(CexBotAsset::BotId).big_integer().not_null())
+                    .col(ColumnDef::new(CexBotAsset::Asset).string_len(16).not_null())
+                    .col(ColumnDef::new(CexBotAsset::InitialMargin).string_len(64).not_null())
+                    .col(ColumnDef::new(CexBotAsset::MaintMargin).string_len(64).not_null())
+                    .col(ColumnDef::new(CexBotAsset::MarginBalance).string_len(64).not_null())
+                    .col(ColumnDef::new(CexBotAsset::OpenOrderInitialMargin).string_len(64).not_null())
+                    .col(ColumnDef::new(CexBotAsset::PositionInitialMargin).string_len(64).not_null())
+                    .col(ColumnDef::new(CexBotAsset::UnrealizedProfit).string_len(64).not_null())
+                    .col(ColumnDef::new(CexBotAsset::WalletBalance).string_len(64).not_null())
+                    .col(ColumnDef::new(CexBotAsset::CrossWalletBalance).string_len(64).not_null())
+                    .col(ColumnDef::new(CexBotAsset::CrossUnPnl).string_len(64).not_null())
+                    .col(ColumnDef::new(CexBotAsset::AvailableBalance).string_len(64).not_null())
+                    .col(ColumnDef::new(CexBotAsset::MarginAvailable).small_integer().null())
+                    .col(
+                        ColumnDef::new(CexBotAsset::CreatedAt)
+                            .date_time()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .col(
+                        ColumnDef::new(CexBotAsset::UpdatedAt)
+                            .date_time()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .col(ColumnDef::new(CexBotAsset::DeletedAt).date_time().null())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_cex_bot_asset_bot_id_asset")
+                    .table(CexBotAsset::Table)
+                    .col(CexBotAsset::BotId)
+                    .col(CexBotAsset::Asset)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(CexBotAsset::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum CexBotAsset {
+    Table,
+    Id,
+    BotId,
+    Asset,
+    InitialMargin,
+    MaintMargin,
+    MarginBalance,
+    OpenOrderInitialMargin,
+    PositionInitialMargin,
+    UnrealizedProfit,
+    WalletBalance,
+    CrossWalletBalance,
+    CrossUnPnl,
+    AvailableBalance,
+    MarginAvailable,
+    CreatedAt,
+    UpdatedAt,
+    DeletedAt,
+}
@@ -0,0 +1,98 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(CexBotPosition::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(CexBotPosition::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(CexBotPosition::BotId).big_integer().not_null())
+                    .col(ColumnDef::new(CexBotPosition::Isolated).small_integer().not_null())
+                    .col(ColumnDef::new(CexBotPosition::Leverage).string_len(64).not_null())
+                    .col(ColumnDef::new(CexBotPosition::InitialMargin).string_len(64).not_null())
+                    .col(ColumnDef::new(CexBotPosition::MaintMargin).string_len(64).not_null())
+                    .col(ColumnDef::new(CexBotPosition::OpenOrderInitialMargin).string_len(64).not_null())
+                    .col(ColumnDef::new(CexBotPosition::PositionInitialMargin).string_len(64).not_null())
+                    .col(ColumnDef::new(CexBotPosition::Symbol).string_len(32).not_null())
+                    .col(ColumnDef::new(CexBotPosition::UnrealizedProfit).string_len(64).not_null())
+                    .col(ColumnDef::new(CexBotPosition::EntryPrice).string_len(64).not_null())
+                    .col(ColumnDef::new(CexBotPosition::MaxNotional).string_len(64).not_null())
+                    .col(ColumnDef::new(CexBotPosition::Side).string_len(8).not_null())
+                    .col(ColumnDef::new(CexBotPosition::Amount).string_len(64).not_null())
+                    .col(ColumnDef::new(CexBotPosition::Notional).string_len(64).not_null())
+                    .col(ColumnDef::new(CexBotPosition::BidNotional).string_len(64).not_null())
+                    .col(ColumnDef::new(CexBotPosition::AskNotional).string_len(64).not_null())
+                    .col(
+                        ColumnDef::new(CexBotPosition::CreatedAt)
+                            .date_time()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .col(
+                        ColumnDef::new(CexBotPosition::UpdatedAt)
+                            .date_time()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .col(ColumnDef::new(CexBotPosition::DeletedAt).date_time().null())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_cex_bot_position_bot_id_symbol_side")
+                    .table(CexBotPosition::Table)
+                    .col(CexBotPosition::BotId)
+                    .col(CexBotPosition::Symbol)
+                    .col(CexBotPosition::Side)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(CexBotPosition::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum CexBotPosition {
+    Table,
+    Id,
+    BotId,
+    Isolated,
+    Leverage,
+    InitialMargin,
+    MaintMargin,
+    OpenOrderInitialMargin,
+    PositionInitialMargin,
+    Symbol,
+    UnrealizedProfit,
+    EntryPrice,
+    MaxNotional,
+    Side,
+    Amount,
+    Notional,
+    BidNotional,
+    AskNotional,
+    CreatedAt,
+    UpdatedAt,
+    DeletedAt,
+}
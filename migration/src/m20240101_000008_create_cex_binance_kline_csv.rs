@@ -0,0 +1,75 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(CexBinanceKlineCsv::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(CexBinanceKlineCsv::Id)
+                            .unsigned()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(CexBinanceKlineCsv::Event).string_len(32).not_null())
+                    .col(ColumnDef::new(CexBinanceKlineCsv::Symbol).string_len(32).not_null())
+                    .col(ColumnDef::new(CexBinanceKlineCsv::Interval).string_len(8).not_null())
+                    .col(ColumnDef::new(CexBinanceKlineCsv::StartTime).big_integer().null())
+                    .col(ColumnDef::new(CexBinanceKlineCsv::EndTime).big_integer().null())
+                    .col(
+                        ColumnDef::new(CexBinanceKlineCsv::CreatedAt)
+                            .date_time()
+                            .null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .col(
+                        ColumnDef::new(CexBinanceKlineCsv::UpdatedAt)
+                            .date_time()
+                            .null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .col(ColumnDef::new(CexBinanceKlineCsv::DeletedAt).date_time().null())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_cex_binance_kline_csv_event_symbol_interval")
+                    .table(CexBinanceKlineCsv::Table)
+                    .col(CexBinanceKlineCsv::Event)
+                    .col(CexBinanceKlineCsv::Symbol)
+                    .col(CexBinanceKlineCsv::Interval)
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(CexBinanceKlineCsv::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum CexBinanceKlineCsv {
+    Table,
+    Id,
+    Event,
+    Symbol,
+    Interval,
+    StartTime,
+    EndTime,
+    CreatedAt,
+    UpdatedAt,
+    DeletedAt,
+}
@@ -0,0 +1,70 @@
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::sea_orm::DatabaseBackend;
+
+/// Order ids are read as `i64`, which sqlx refuses to decode from
+/// `BIGINT UNSIGNED`. Only MySQL has unsigned columns; postgres and sqlite
+/// already store them as `bigint`.
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        if manager.get_database_backend() != DatabaseBackend::MySql {
+            return Ok(());
+        }
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(CexBotOrder::Table)
+                    .modify_column(ColumnDef::new(CexBotOrder::OrderId).big_integer().not_null())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(CexBotSellOrder::Table)
+                    .modify_column(ColumnDef::new(CexBotSellOrder::OrderId).big_integer().not_null())
+                    .modify_column(ColumnDef::new(CexBotSellOrder::CancelOrderId).big_integer().null())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        if manager.get_database_backend() != DatabaseBackend::MySql {
+            return Ok(());
+        }
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(CexBotOrder::Table)
+                    .modify_column(ColumnDef::new(CexBotOrder::OrderId).big_unsigned().not_null())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(CexBotSellOrder::Table)
+                    .modify_column(ColumnDef::new(CexBotSellOrder::OrderId).big_unsigned().not_null())
+                    .modify_column(ColumnDef::new(CexBotSellOrder::CancelOrderId).big_unsigned().null())
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum CexBotOrder {
+    Table,
+    OrderId,
+}
+
+#[derive(DeriveIden)]
+enum CexBotSellOrder {
+    Table,
+    OrderId,
+    CancelOrderId,
+}
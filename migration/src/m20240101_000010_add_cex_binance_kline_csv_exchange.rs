@@ -0,0 +1,66 @@
+use sea_orm_migration::prelude::*;
+
+/// Aggregated trades and liquidations are stored per exchange and market, so
+/// a recorded import must say which one it covers. Rows recorded before carry
+/// empty values and are imported once more.
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // sqlite takes one column per ALTER TABLE
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(CexBinanceKlineCsv::Table)
+                    .add_column(
+                        ColumnDef::new(CexBinanceKlineCsv::Exchange)
+                            .string_len(32)
+                            .not_null()
+                            .default(""),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(CexBinanceKlineCsv::Table)
+                    .add_column(
+                        ColumnDef::new(CexBinanceKlineCsv::Market)
+                            .string_len(32)
+                            .not_null()
+                            .default(""),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(CexBinanceKlineCsv::Table)
+                    .drop_column(CexBinanceKlineCsv::Market)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(CexBinanceKlineCsv::Table)
+                    .drop_column(CexBinanceKlineCsv::Exchange)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum CexBinanceKlineCsv {
+    Table,
+    Exchange,
+    Market,
+}